@@ -3,12 +3,19 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::env;
 use std::fs;
-use std::net::IpAddr;
 use std::path::PathBuf;
+use std::time::Duration;
+use toml_edit::{Array, DocumentMut, Item, Key, TableLike};
 
-use super::scene::Scene;
+use super::scene::{Scene, SceneSettings};
+use crate::coap::{Backend, RetryPolicy};
+use crate::discovery;
+use crate::tradfri::Light;
 
 const CONFIG_FILENAME: &str = "config.toml";
+/// Upper bound for every seconds setting: longer than any fade or timeout
+/// makes sense, and far below where `Duration` overflows.
+const MAX_SECONDS: f32 = 86_400.0;
 
 // ── Config ──────────────────────────────────────────────
 
@@ -22,14 +29,72 @@ pub struct Config {
     pub scenes: ScenesConfig,
 }
 
+impl Config {
+    /// Reject times TOML accepts but a `Duration` can't hold (`inf`, `nan`,
+    /// `1e30`), so a typo is a config error rather than a crash later.
+    fn validate(&self) -> Result<()> {
+        let mut times = vec![
+            ("gateway.ack_timeout".to_string(), Some(self.gateway.ack_timeout)),
+            ("ui.transition".to_string(), self.ui.transition),
+            ("scenes.transition".to_string(), self.scenes.transition),
+            ("scenes.fade_minutes".to_string(), Some(self.scenes.fade_minutes * 60.0)),
+        ];
+        times.extend(
+            self.scenes
+                .transition_by_scene
+                .iter()
+                .map(|(scene, secs)| (format!("scenes.transition_by_scene.{}", scene), Some(*secs))),
+        );
+        for (key, secs) in times {
+            if let Some(secs) = secs.filter(|s| !(s.is_finite() && *s <= MAX_SECONDS)) {
+                anyhow::bail!("{} is {} seconds; the most allowed is {} (one day)", key, secs, MAX_SECONDS);
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GatewayConfig {
+    /// Gateway address (IP, IPv6 or host name, optionally with `:port`), or
+    /// `"auto"` / empty to discover it via mDNS.
     #[serde(default = "default_host")]
     pub host: String,
     #[serde(default)]
     pub identity: String,
     #[serde(default)]
     pub psk: String,
+    /// Seconds to wait for a reply before retransmitting a request; doubles
+    /// with each retransmission.
+    #[serde(default = "default_ack_timeout")]
+    pub ack_timeout: f32,
+    /// Retransmissions on the same DTLS session before reconnecting.
+    #[serde(default = "default_max_retransmit")]
+    pub max_retransmit: u32,
+    /// Full DTLS reconnects per request before giving up.
+    #[serde(default = "default_reconnects")]
+    pub reconnects: u32,
+}
+
+impl GatewayConfig {
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            // A zero timeout would spin; keep at least a few milliseconds.
+            ack_timeout: seconds(self.ack_timeout).max(Duration::from_millis(10)),
+            max_retransmit: self.max_retransmit,
+            reconnects: self.reconnects,
+        }
+    }
+
+    /// The configured gateway as a session backend.
+    pub fn backend(&self) -> Backend {
+        Backend::Dtls {
+            host: self.host.clone(),
+            identity: self.identity.clone(),
+            psk: self.psk.clone(),
+            retry: self.retry_policy(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,9 +103,22 @@ pub struct UiConfig {
     pub theme: String,
     #[serde(default = "default_refresh")]
     pub refresh_interval: u64,
+    /// Fade time in seconds for toggling, dimming and colour changes.
+    /// Unset uses the gateway's default.
+    #[serde(default)]
+    pub transition: Option<f32>,
+    /// Battery percentage at or below which a device counts as low.
+    #[serde(default = "default_battery_warning")]
+    pub battery_warning: u8,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+impl UiConfig {
+    pub fn transition(&self) -> Option<Duration> {
+        self.transition.map(seconds)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScenesConfig {
     /// Light names to exclude from all scene commands.
     /// Example: exclude = ["Sovrummet", "Barnrummet"]
@@ -51,9 +129,37 @@ pub struct ScenesConfig {
     /// Example: exclude_by_scene = { movie = ["TV Lamp"], night = ["Kitchen"] }
     #[serde(default)]
     pub exclude_by_scene: HashMap<String, Vec<String>>,
+
+    /// Blind positions (0 = open, 100 = closed) by scene key, then blind name.
+    /// Example: blinds_by_scene = { movie = { "Vardagsrum" = 100 } }
+    #[serde(default)]
+    pub blinds_by_scene: HashMap<String, HashMap<String, u8>>,
+
+    /// Whether scenes switch smart plugs (on/off only). Individual plugs can
+    /// still be left out by name via `exclude` / `exclude_by_scene`.
+    #[serde(default = "default_include_plugs")]
+    pub include_plugs: bool,
+
+    /// Fade time in seconds for scenes without a built-in fade.
+    #[serde(default)]
+    pub transition: Option<f32>,
+
+    /// Scene-specific fade times in seconds by scene key.
+    /// Example: transition_by_scene = { movie = 5.0, off = 2.0 }
+    #[serde(default)]
+    pub transition_by_scene: HashMap<String, f32>,
+
+    /// Length of a slow fade (`f` in the scene picker, `--fade` default).
+    #[serde(default = "default_fade_minutes")]
+    pub fade_minutes: f32,
 }
 
 impl ScenesConfig {
+    /// True if `scene` should change `light`.
+    pub fn includes(&self, scene: Scene, light: &Light) -> bool {
+        (self.include_plugs || !light.plug) && !self.is_excluded_for_scene(scene, &light.name)
+    }
+
     pub fn is_excluded_for_scene(&self, scene: Scene, light_name: &str) -> bool {
         if self
             .exclude
@@ -69,23 +175,113 @@ impl ScenesConfig {
                 && names.iter().any(|e| e.eq_ignore_ascii_case(light_name))
         })
     }
+
+    /// How many `exclude`, `exclude_by_scene` and `blinds_by_scene` entries
+    /// name `name`; these go stale when the device is renamed.
+    pub fn name_references(&self, name: &str) -> usize {
+        let listed = self
+            .exclude
+            .iter()
+            .chain(self.exclude_by_scene.values().flatten())
+            .filter(|e| e.eq_ignore_ascii_case(name))
+            .count();
+        let blinds = self
+            .blinds_by_scene
+            .values()
+            .flat_map(|blinds| blinds.keys())
+            .filter(|b| b.eq_ignore_ascii_case(name))
+            .count();
+        listed + blinds
+    }
+
+    /// Point the entries counted by `name_references` at `new` instead.
+    pub fn rename(&mut self, old: &str, new: &str) {
+        for entry in self.exclude.iter_mut().chain(self.exclude_by_scene.values_mut().flatten()) {
+            if entry.eq_ignore_ascii_case(old) {
+                *entry = new.to_string();
+            }
+        }
+        for blinds in self.blinds_by_scene.values_mut() {
+            let matching: Vec<String> = blinds.keys().filter(|b| b.eq_ignore_ascii_case(old)).cloned().collect();
+            for name in matching {
+                if let Some(position) = blinds.remove(&name) {
+                    blinds.insert(new.to_string(), position);
+                }
+            }
+        }
+    }
+
+    /// Position `scene` moves the blind named `blind_name` to, if any.
+    pub fn blind_position(&self, scene: Scene, blind_name: &str) -> Option<u8> {
+        let scene_key = scene.config_key();
+        self.blinds_by_scene
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case(scene_key))
+            .flat_map(|(_, blinds)| blinds)
+            .find(|(name, _)| name.eq_ignore_ascii_case(blind_name))
+            .map(|(_, position)| (*position).min(100))
+    }
+
+    /// True if any blind has a position configured for `scene`.
+    pub fn moves_blinds(&self, scene: Scene) -> bool {
+        let scene_key = scene.config_key();
+        self.blinds_by_scene
+            .iter()
+            .any(|(key, blinds)| key.eq_ignore_ascii_case(scene_key) && !blinds.is_empty())
+    }
+
+    /// Duration of a slow fade.
+    pub fn fade_duration(&self) -> Duration {
+        seconds(self.fade_minutes * 60.0)
+    }
+
+    /// Default fade for scenes and gateway moods.
+    pub fn transition(&self) -> Option<Duration> {
+        self.transition.map(seconds)
+    }
+
+    /// The scene's settings with its fade time resolved: a per-scene override
+    /// wins, then the scene's built-in fade, then the `[scenes]` default.
+    pub fn settings_for(&self, scene: Scene) -> SceneSettings {
+        let mut settings = scene.settings();
+        let scene_key = scene.config_key();
+        let configured = self
+            .transition_by_scene
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(scene_key))
+            .map(|(_, secs)| seconds(*secs));
+        settings.transition = configured
+            .or(settings.transition)
+            .or(self.transition());
+        settings
+    }
+}
+
+/// Config seconds → Duration; negative values mean "instant". Out-of-range
+/// values are refused at load time, but clamp here too rather than panic.
+fn seconds(secs: f32) -> Duration {
+    Duration::try_from_secs_f32(secs.clamp(0.0, MAX_SECONDS)).unwrap_or_default()
 }
 
-fn default_host() -> String { "192.168.0.131".to_string() }
+fn default_host() -> String { "auto".to_string() }
+fn default_ack_timeout() -> f32 { 1.0 }
+fn default_max_retransmit() -> u32 { 2 }
+fn default_reconnects() -> u32 { 1 }
 fn default_theme() -> String { "auto".to_string() }
 fn default_refresh() -> u64 { 5 }
+fn default_battery_warning() -> u8 { 20 }
+fn default_fade_minutes() -> f32 { 20.0 }
+fn default_include_plugs() -> bool { true }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
-            gateway: GatewayConfig {
-                host: default_host(),
-                identity: String::new(),
-                psk: String::new(),
-            },
+            gateway: GatewayConfig::default(),
             ui: UiConfig {
                 theme: default_theme(),
                 refresh_interval: default_refresh(),
+                transition: None,
+                battery_warning: default_battery_warning(),
             },
             scenes: ScenesConfig::default(),
         }
@@ -98,6 +294,23 @@ impl Default for GatewayConfig {
             host: default_host(),
             identity: String::new(),
             psk: String::new(),
+            ack_timeout: default_ack_timeout(),
+            max_retransmit: default_max_retransmit(),
+            reconnects: default_reconnects(),
+        }
+    }
+}
+
+impl Default for ScenesConfig {
+    fn default() -> Self {
+        Self {
+            exclude: Vec::new(),
+            exclude_by_scene: HashMap::new(),
+            blinds_by_scene: HashMap::new(),
+            include_plugs: default_include_plugs(),
+            transition: None,
+            transition_by_scene: HashMap::new(),
+            fade_minutes: default_fade_minutes(),
         }
     }
 }
@@ -107,6 +320,8 @@ impl Default for UiConfig {
         Self {
             theme: default_theme(),
             refresh_interval: default_refresh(),
+            transition: None,
+            battery_warning: default_battery_warning(),
         }
     }
 }
@@ -154,13 +369,13 @@ pub fn load_config() -> Result<Config> {
                 .with_context(|| format!("Failed to read {}", path.display()))?;
             let config: Config = toml::from_str(&content)
                 .with_context(|| format!("Failed to parse {}", path.display()))?;
+            config
+                .validate()
+                .with_context(|| format!("Invalid setting in {}", path.display()))?;
 
-            if !config.gateway.host.is_empty() {
-                config
-                    .gateway
-                    .host
-                    .parse::<IpAddr>()
-                    .with_context(|| format!("Invalid gateway IP: '{}'", config.gateway.host))?;
+            if !discovery::is_auto(&config.gateway.host) {
+                discovery::parse_host(&config.gateway.host)
+                    .with_context(|| format!("Invalid gateway host: '{}'", config.gateway.host))?;
             }
 
             return Ok(config);
@@ -168,6 +383,22 @@ pub fn load_config() -> Result<Config> {
     }
 
     // Auto-generate default config
+    let path = write_default_config()?;
+    eprintln!("Generated default config at: {}", path.display());
+    eprintln!("Edit it with your gateway credentials before running FrostLux.");
+    Ok(Config::default())
+}
+
+/// The user's own config file (never the bundled `config/default.toml`).
+fn user_config_path() -> PathBuf {
+    config_paths()
+        .into_iter()
+        .take_while(|p| p.ends_with(CONFIG_FILENAME))
+        .find(|p| p.exists())
+        .unwrap_or_else(|| config_dir().join(CONFIG_FILENAME))
+}
+
+fn write_default_config() -> Result<PathBuf> {
     let dir = config_dir();
     fs::create_dir_all(&dir)?;
     let path = dir.join(CONFIG_FILENAME);
@@ -176,12 +407,17 @@ pub fn load_config() -> Result<Config> {
         "# FrostLux Configuration\n\
          # Edit this file to customize FrostLux behavior.\n\n\
          [gateway]\n\
-         host = \"{}\"\n\
+         host = \"{}\"  # \"auto\" discovers the gateway via mDNS; or IP / host name[:port]\n\
          identity = \"\"  # From gateway pairing\n\
-         psk = \"\"        # Pre-shared key\n\n\
+         psk = \"\"        # Pre-shared key\n\
+         ack_timeout = 1.0  # seconds before retransmitting a request (doubles each time)\n\
+         max_retransmit = 2  # retransmissions before a full DTLS reconnect\n\
+         reconnects = 1  # reconnects per request before giving up\n\n\
          [ui]\n\
          theme = \"auto\"  # auto, light, dark\n\
-         refresh_interval = 5\n\n\
+         refresh_interval = 5  # fallback poll (seconds) if observe fails\n\
+         # transition = 0.5  # fade (seconds) for toggles and dimming\n\
+         battery_warning = 20  # % at which remotes, sensors and blinds count as low\n\n\
          [scenes]\n\
          # Lights to exclude from all scene commands:\n\
          # exclude = [\"Sovrummet\", \"Barnrummet\"]\n\
@@ -189,11 +425,345 @@ pub fn load_config() -> Result<Config> {
          # Exclude only for specific scenes (keys: on, off, movie, bright,\n\
          # cozy, night, evening, reading, morning)\n\
          # exclude_by_scene = {{ movie = [\"TV\"], night = [\"Kitchen\"] }}\n\
-         exclude_by_scene = {{}}\n",
+         exclude_by_scene = {{}}\n\
+         include_plugs = true  # switch smart plugs with scenes\n\
+         # Blind positions per scene (0 = open, 100 = closed):\n\
+         # blinds_by_scene = {{ movie = {{ \"Vardagsrum\" = 100 }} }}\n\
+         # Fade (seconds) for scenes; Movie and Night fade by default.\n\
+         # transition = 1.0\n\
+         # transition_by_scene = {{ night = 10.0 }}\n\
+         fade_minutes = 20  # slow fade (f in the scene picker, --fade)\n",
         default.gateway.host
     );
     fs::write(&path, &content)?;
-    eprintln!("Generated default config at: {}", path.display());
-    eprintln!("Edit it with your gateway credentials before running FrostLux.");
-    Ok(default)
+    Ok(path)
+}
+
+/// Write `host`, `identity` and `psk` into the `[gateway]` section of the
+/// user's config, creating the file if needed. Other lines and comments are
+/// left untouched. Returns the path written.
+pub fn save_gateway_credentials(host: &str, identity: &str, psk: &str) -> Result<PathBuf> {
+    let path = user_config_path();
+    if !path.exists() {
+        write_default_config()?;
+    }
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let updated = set_table_values(
+        &content,
+        "gateway",
+        &[("host", host), ("identity", identity), ("psk", psk)],
+    )
+    .with_context(|| format!("Failed to parse {}", path.display()))?;
+    fs::write(&path, updated).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+// ── Paired identities ───────────────────────────────────
+
+const IDENTITIES_FILENAME: &str = "identities.toml";
+
+/// An identity `frostlux pair` registered with a gateway. The gateway can't
+/// list the identities it knows, so FrostLux keeps its own record of them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PairedIdentity {
+    pub name: String,
+    pub host: String,
+    pub psk: String,
+    /// Unix seconds
+    pub paired: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct IdentitiesFile {
+    #[serde(default, rename = "identity")]
+    identities: Vec<PairedIdentity>,
+}
+
+fn identities_path() -> PathBuf {
+    config_dir().join(IDENTITIES_FILENAME)
+}
+
+/// Identities paired from this machine, oldest first.
+pub fn load_identities() -> Result<Vec<PairedIdentity>> {
+    let path = identities_path();
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let file: IdentitiesFile =
+        toml::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))?;
+    Ok(file.identities)
+}
+
+fn save_identities(identities: Vec<PairedIdentity>) -> Result<PathBuf> {
+    let path = identities_path();
+    fs::create_dir_all(config_dir())?;
+    let content = format!(
+        "# Identities FrostLux paired with a gateway (frostlux gateway identities)\n\n{}",
+        toml::to_string(&IdentitiesFile { identities })?
+    );
+    fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// Add `identity` to the record, replacing an entry of the same name and host.
+pub fn record_identity(identity: PairedIdentity) -> Result<PathBuf> {
+    let mut identities = load_identities()?;
+    identities.retain(|i| i.name != identity.name || i.host != identity.host);
+    identities.push(identity);
+    save_identities(identities)
+}
+
+/// Drop `names` from the record. Returns how many entries went.
+pub fn forget_identities(names: &[String]) -> Result<usize> {
+    let mut identities = load_identities()?;
+    let before = identities.len();
+    identities.retain(|i| !names.contains(&i.name));
+    let removed = before - identities.len();
+    if removed > 0 {
+        save_identities(identities)?;
+    }
+    Ok(removed)
+}
+
+/// Rename `old` to `new` in the `[scenes]` name lists (`exclude`,
+/// `exclude_by_scene`, `blinds_by_scene`) of the user's config. Other lines
+/// and comments are left untouched. Returns how many entries changed and how
+/// many still name `old` (blinds already listed under `new` for a scene).
+pub fn rename_in_scenes_config(old: &str, new: &str) -> Result<(usize, usize)> {
+    let path = user_config_path();
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let (updated, count) =
+        rename_scene_names(&content, old, new).with_context(|| format!("Failed to parse {}", path.display()))?;
+    // Never leave a config behind that no longer loads.
+    let config = toml::from_str::<Config>(&updated).context("Renaming would break the config")?;
+    let skipped = config.scenes.name_references(old);
+    if count > 0 {
+        fs::write(&path, updated).with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok((count, skipped))
+}
+
+/// Replace every name equal to `old` (ignoring ASCII case) with `new` where
+/// the scene settings list lights and blinds: `exclude`, the lists in
+/// `exclude_by_scene` and the keys of each `blinds_by_scene` scene, however
+/// they are written (inline, dotted keys or `[scenes.*]` tables). Returns
+/// the new content and the number of replacements.
+fn rename_scene_names(content: &str, old: &str, new: &str) -> Result<(String, usize)> {
+    let mut doc: DocumentMut = content.parse()?;
+    let Some(scenes) = doc.get_mut("scenes").and_then(Item::as_table_like_mut) else {
+        return Ok((content.to_string(), 0));
+    };
+    let mut count = 0;
+    if let Some(exclude) = scenes.get_mut("exclude").and_then(Item::as_array_mut) {
+        count += rename_strings(exclude, old, new);
+    }
+    if let Some(by_scene) = scenes.get_mut("exclude_by_scene").and_then(Item::as_table_like_mut) {
+        for (_, list) in by_scene.iter_mut() {
+            if let Some(list) = list.as_array_mut() {
+                count += rename_strings(list, old, new);
+            }
+        }
+    }
+    if let Some(by_scene) = scenes.get_mut("blinds_by_scene").and_then(Item::as_table_like_mut) {
+        for (_, blinds) in by_scene.iter_mut() {
+            if let Some(blinds) = blinds.as_table_like_mut() {
+                count += rename_keys(blinds, old, new);
+            }
+        }
+    }
+    Ok((doc.to_string(), count))
+}
+
+/// Rename matching strings in `array`, keeping the comments around them.
+fn rename_strings(array: &mut Array, old: &str, new: &str) -> usize {
+    let mut count = 0;
+    for value in array.iter_mut() {
+        if value.as_str().is_some_and(|name| name.eq_ignore_ascii_case(old)) {
+            let decor = value.decor().clone();
+            *value = new.into();
+            *value.decor_mut() = decor;
+            count += 1;
+        }
+    }
+    count
+}
+
+/// Rename matching keys in `table`. A key that would clash with one already
+/// there is left alone.
+fn rename_keys(table: &mut dyn TableLike, old: &str, new: &str) -> usize {
+    let matching: Vec<String> = table
+        .iter()
+        .map(|(key, _)| key.to_string())
+        .filter(|key| key.eq_ignore_ascii_case(old) && !table.contains_key(new))
+        .collect();
+    let mut count = 0;
+    for name in matching {
+        let Some((key, _)) = table.get_key_value(&name) else { continue };
+        let key = Key::new(new)
+            .with_leaf_decor(key.leaf_decor().clone())
+            .with_dotted_decor(key.dotted_decor().clone());
+        let Some(item) = table.remove(&name) else { continue };
+        table.entry_format(&key).or_insert(item);
+        count += 1;
+    }
+    count
+}
+
+/// Set string `key = "value"` pairs in `[table]`, adding the table or keys
+/// that are missing. Comments and the rest of the file are kept.
+fn set_table_values(content: &str, table: &str, values: &[(&str, &str)]) -> Result<String> {
+    let mut doc: DocumentMut = content.parse()?;
+    let table = doc
+        .entry(table)
+        .or_insert_with(toml_edit::table)
+        .as_table_like_mut()
+        .with_context(|| format!("'{}' isn't a table", table))?;
+    for (key, value) in values {
+        match table.get_mut(key).and_then(Item::as_value_mut) {
+            Some(current) => {
+                let decor = current.decor().clone();
+                *current = (*value).into();
+                *current.decor_mut() = decor;
+            }
+            None => {
+                table.insert(key, toml_edit::value(*value));
+            }
+        }
+    }
+    Ok(doc.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unusable_times_are_config_errors() {
+        for bad in ["[ui]\ntransition = inf", "[gateway]\nack_timeout = 1e30", "[scenes]\nfade_minutes = nan"] {
+            let config: Config = toml::from_str(bad).unwrap();
+            assert!(config.validate().is_err(), "{}", bad);
+        }
+        let config: Config = toml::from_str("[ui]\ntransition = -1.0").unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.ui.transition(), Some(Duration::ZERO));
+        assert_eq!(seconds(f32::INFINITY), Duration::from_secs(86_400));
+        assert_eq!(seconds(f32::NAN), Duration::ZERO);
+    }
+
+    #[test]
+    fn set_table_values_keeps_comments_but_not_hashes_in_values() {
+        let content = "[gateway]\nidentity = \"a #b\"  # from pairing\npsk = 'x#y'\n";
+        let updated = set_table_values(content, "gateway", &[("identity", "new"), ("psk", "key")]).unwrap();
+        assert_eq!(updated, "[gateway]\nidentity = \"new\"  # from pairing\npsk = \"key\"\n");
+    }
+
+    #[test]
+    fn set_table_values_appends_missing_keys_and_tables() {
+        let updated = set_table_values("[ui]\ntheme = \"auto\"\n", "gateway", &[("host", "10.0.0.2")]).unwrap();
+        assert_eq!(updated, "[ui]\ntheme = \"auto\"\n\n[gateway]\nhost = \"10.0.0.2\"\n");
+
+        let content = "[gateway]\nhost = \"auto\"\n\n[ui]\ntheme = \"auto\"\n";
+        let updated = set_table_values(content, "gateway", &[("psk", "key")]).unwrap();
+        assert_eq!(updated, "[gateway]\nhost = \"auto\"\npsk = \"key\"\n\n[ui]\ntheme = \"auto\"\n");
+    }
+
+    #[test]
+    fn set_table_values_is_not_fooled_by_brackets_in_arrays() {
+        // Lines of a multi-line array that start with `[` are not headers.
+        let content = "\
+[gateway]
+host = \"auto\"
+
+[other]
+matrix = [
+  [1, 2],
+  [3, 4],
+]
+";
+        let updated = set_table_values(content, "gateway", &[("psk", "key")]).unwrap();
+        assert!(updated.starts_with("[gateway]\nhost = \"auto\"\npsk = \"key\"\n"));
+        assert!(updated.ends_with("matrix = [\n  [1, 2],\n  [3, 4],\n]\n"));
+        assert!(set_table_values("[gateway\n", "gateway", &[]).is_err());
+    }
+
+    #[test]
+    fn rename_scene_names_follows_scene_tables() {
+        let content = "\
+[gateway]
+identity = \"TV\"
+
+[scenes]
+exclude = [
+  \"TV\",  # stays on
+  \"Hall\",
+]
+exclude_by_scene.night = [\"tv\"]
+
+[scenes.blinds_by_scene]
+night = { \"TV\" = 20, Hall = 0 }
+evening = { TV = 20 }
+
+[scenes.blinds_by_scene.movie]
+TV = 100
+\"Hall\" = 50
+";
+        let (updated, count) = rename_scene_names(content, "TV", "Tv-hörnan").unwrap();
+        assert_eq!(count, 5);
+        assert!(updated.starts_with("[gateway]\nidentity = \"TV\"\n"));
+        assert!(updated.contains("  \"Tv-hörnan\",  # stays on\n"));
+        let config: Config = toml::from_str(&updated).unwrap();
+        assert_eq!(config.scenes.name_references("Tv-hörnan"), 5);
+        assert_eq!(config.scenes.name_references("TV"), 0);
+    }
+
+    #[test]
+    fn rename_scene_names_skips_arrays_outside_scenes() {
+        // A multi-line array in another table, with lines that look like
+        // headers, must neither be renamed in nor end the scene settings.
+        let content = "\
+[scenes]
+exclude = [\"TV\"]
+
+[other]
+names = [
+  [\"TV\"],
+  [\"scenes\"],
+]
+list = [
+  \"TV\",
+]
+
+[scenes.exclude_by_scene]
+movie = [
+  \"TV\",
+]
+";
+        let (updated, count) = rename_scene_names(content, "TV", "Tv-hörnan").unwrap();
+        assert_eq!(count, 2);
+        assert!(updated.contains("names = [\n  [\"TV\"],\n  [\"scenes\"],\n]\nlist = [\n  \"TV\",\n]\n"));
+        assert!(updated.contains("movie = [\n  \"Tv-hörnan\",\n]\n"));
+    }
+
+    #[test]
+    fn rename_scene_names_handles_dotted_and_quoted_keys() {
+        let content = "\
+scenes.exclude = ['TV']
+scenes.exclude_by_scene.\"movie\" = [\"Hall\", \"tv\"]
+scenes.blinds_by_scene.night.\"TV\" = 20
+scenes.blinds_by_scene.\"evening\".'TV' = 40  # half down
+scenes.blinds_by_scene.movie = { \"TV\" = 100, \"Tv-hörnan\" = 90 }
+";
+        let (updated, count) = rename_scene_names(content, "TV", "Tv-hörnan").unwrap();
+        // The movie blind already has an entry under the new name.
+        assert_eq!(count, 4);
+        assert!(updated.contains("40  # half down\n"));
+        let config: Config = toml::from_str(&updated).unwrap();
+        assert_eq!(config.scenes.name_references("Tv-hörnan"), 5);
+        assert_eq!(config.scenes.name_references("TV"), 1);
+        assert_eq!(config.scenes.blinds_by_scene["evening"]["Tv-hörnan"], 40);
+    }
 }
@@ -0,0 +1,258 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use super::scene::SceneSettings;
+use crate::coap::{SceneState, SharedTradfriClient};
+use crate::tradfri::Light;
+
+// ── Fades ───────────────────────────────────────────────
+
+/// Time between fade steps. Each step is sent with this as its transition,
+/// so the bulb glides from one step to the next instead of stepping.
+const FADE_STEP: Duration = Duration::from_secs(2);
+/// How often the fade thread checks for cancellation between steps.
+const CANCEL_POLL: Duration = Duration::from_millis(200);
+/// Slack when comparing reported state against what the fade sent: a light
+/// further away than this was changed by someone else (remote, app, TUI).
+const BRIGHTNESS_TOLERANCE: u8 = 12;
+const MIREDS_TOLERANCE: u16 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Level {
+    on: bool,
+    brightness: u8,
+    mireds: Option<u16>,
+}
+
+impl Level {
+    /// Effective brightness: an off light counts as 0.
+    fn lit(&self) -> u8 {
+        if self.on { self.brightness } else { 0 }
+    }
+}
+
+/// One light's path through the fade.
+struct Track {
+    id: u64,
+    name: String,
+    /// Plugs can't dim: they keep their state and switch on the last step.
+    plug: bool,
+    from: Level,
+    to: Level,
+    /// The last two levels sent (or the starting level), newest last. A
+    /// notification may describe either while the gateway is mid-transition.
+    sent: [Level; 2],
+    cancelled: bool,
+}
+
+impl Track {
+    fn level_at(&self, progress: f32) -> Level {
+        if progress >= 1.0 {
+            return self.to;
+        }
+        if self.plug {
+            return self.from;
+        }
+        let lerp = |a: f32, b: f32| a + (b - a) * progress;
+        let brightness = lerp(self.from.lit() as f32, self.to.lit() as f32).round() as u8;
+        let mireds = match (self.from.mireds, self.to.mireds) {
+            (Some(a), Some(b)) => Some(lerp(a as f32, b as f32).round() as u16),
+            (_, to) => to,
+        };
+        // Stay on (at least at the lowest level) until the final step, so a
+        // fade to off doesn't cut out early.
+        Level { on: true, brightness: brightness.max(1), mireds }
+    }
+
+    /// True if `light` can't be explained by what the fade sent.
+    fn touched_by(&self, light: &Light) -> bool {
+        let [a, b] = self.sent;
+        if light.on != a.on && light.on != b.on {
+            return true;
+        }
+        if !light.on || self.plug {
+            return false;
+        }
+        let (lo, hi) = (a.lit().min(b.lit()), a.lit().max(b.lit()));
+        if light.brightness < lo.saturating_sub(BRIGHTNESS_TOLERANCE)
+            || light.brightness > hi.saturating_add(BRIGHTNESS_TOLERANCE)
+        {
+            return true;
+        }
+        match (light.mireds, a.mireds, b.mireds) {
+            (Some(m), Some(x), Some(y)) => {
+                m < x.min(y).saturating_sub(MIREDS_TOLERANCE)
+                    || m > x.max(y).saturating_add(MIREDS_TOLERANCE)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A client-side fade of several lights towards a scene over minutes. The
+/// gateway's own transition is only reliable for a few seconds, so this
+/// sends many small steps instead.
+///
+/// Shared between the thread running it (`run`) and the UI / headless loop,
+/// which cancels lights the user touches.
+pub struct Fade {
+    pub scene_name: String,
+    duration: Duration,
+    started: Instant,
+    tracks: Mutex<Vec<Track>>,
+    stopped: AtomicBool,
+}
+
+impl Fade {
+    pub fn new(
+        scene_name: &str,
+        lights: &[&Light],
+        settings: &SceneSettings,
+        duration: Duration,
+    ) -> Arc<Self> {
+        let tracks = lights
+            .iter()
+            .map(|light| {
+                let target = settings.state(light.mireds.is_some());
+                let from = Level {
+                    on: light.on,
+                    brightness: light.brightness,
+                    mireds: light.mireds,
+                };
+                let to = Level {
+                    on: target.on,
+                    brightness: if target.on { target.brightness } else { 0 },
+                    // Only set for bulbs with colour temperature; kept in their range.
+                    mireds: target.mireds.map(|m| {
+                        let (min, max) = light.mireds_range().unwrap_or((m, m));
+                        m.clamp(min, max)
+                    }),
+                };
+                Track {
+                    id: light.id,
+                    name: light.name.clone(),
+                    plug: light.plug,
+                    from,
+                    to,
+                    sent: [from, from],
+                    cancelled: false,
+                }
+            })
+            .collect();
+        Arc::new(Self {
+            scene_name: scene_name.to_string(),
+            duration,
+            started: Instant::now(),
+            tracks: Mutex::new(tracks),
+            stopped: AtomicBool::new(false),
+        })
+    }
+
+    fn lock_tracks(&self) -> MutexGuard<'_, Vec<Track>> {
+        // A panic mid-step leaves the tracks consistent enough to keep going.
+        self.tracks.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn progress(&self) -> f32 {
+        if self.duration.is_zero() {
+            return 1.0;
+        }
+        (self.started.elapsed().as_secs_f32() / self.duration.as_secs_f32()).min(1.0)
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.duration.saturating_sub(self.started.elapsed())
+    }
+
+    /// Lights still being faded.
+    pub fn active_lights(&self) -> usize {
+        self.lock_tracks().iter().filter(|t| !t.cancelled).count()
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.stopped.load(Ordering::Relaxed)
+    }
+
+    /// Stop the whole fade; lights stay where they are.
+    pub fn cancel(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    /// Leave these lights alone from now on.
+    pub fn cancel_lights(&self, ids: &[u64]) {
+        for track in self.lock_tracks().iter_mut() {
+            if ids.contains(&track.id) {
+                track.cancelled = true;
+            }
+        }
+    }
+
+    /// Feed reported light state (observe or poll). If the light is being
+    /// faded but no longer matches what the fade sent, someone else changed
+    /// it: drop it from the fade and return its name.
+    pub fn observe(&self, light: &Light) -> Option<String> {
+        let mut tracks = self.lock_tracks();
+        let track = tracks
+            .iter_mut()
+            .find(|t| t.id == light.id && !t.cancelled)?;
+        if !track.touched_by(light) {
+            return None;
+        }
+        track.cancelled = true;
+        Some(track.name.clone())
+    }
+
+    /// Step every light until the fade completes, is cancelled, or has no
+    /// lights left. Blocks; run it on its own thread.
+    pub fn run(&self, client: &SharedTradfriClient) {
+        while self.is_running() {
+            let progress = self.progress();
+            let steps: Vec<(u64, String, bool, Level)> = {
+                let mut tracks = self.lock_tracks();
+                if tracks.iter().all(|t| t.cancelled) {
+                    break;
+                }
+                tracks
+                    .iter_mut()
+                    .filter(|t| !t.cancelled)
+                    .filter_map(|t| {
+                        let level = t.level_at(progress);
+                        if level == t.sent[1] {
+                            return None;
+                        }
+                        // Record before sending so the echo isn't taken for a
+                        // manual change.
+                        t.sent = [t.sent[1], level];
+                        Some((t.id, t.name.clone(), t.plug, level))
+                    })
+                    .collect()
+            };
+
+            for (id, name, plug, level) in steps {
+                let state = SceneState {
+                    on: level.on,
+                    brightness: level.brightness,
+                    mireds: level.mireds,
+                };
+                let result = if plug {
+                    client.set_plug_power(id, level.on)
+                } else {
+                    client.apply_scene_to_light(id, state, Some(FADE_STEP))
+                };
+                if let Err(e) = result {
+                    tracing::warn!("fade step failed for '{}': {}", name, e);
+                }
+            }
+
+            if progress >= 1.0 {
+                break;
+            }
+            let next_step = Instant::now() + FADE_STEP;
+            while self.is_running() && Instant::now() < next_step {
+                std::thread::sleep(CANCEL_POLL);
+            }
+        }
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
@@ -1,84 +1,219 @@
 pub mod config;
+pub mod fade;
 pub mod scene;
 
 pub use config::*;
+pub use fade::*;
 pub use scene::*;
 
 use anyhow::Result;
-use std::sync::mpsc;
+use std::sync::{mpsc, Arc};
 use std::time::{Duration, Instant};
 
-use crate::coap::{self, SharedTradfriClient};
-use crate::tradfri::{self, Light, COLOR_TEMP_LABELS, COLOR_TEMPS};
+use crate::coap::{self, Backend, ObserveEvent, SharedTradfriClient};
+use crate::color;
+use crate::tradfri::{
+    self, Blind, Device, Gateway, Group, Light, Mood, Snapshot, COLOR_PALETTE, COLOR_TEMP_LABELS, COLOR_TEMPS,
+    MIREDS_STEP,
+};
 
 /// Ensures the periodic refresh triggers immediately at startup rather than
 /// waiting a full `refresh_interval` before the first background fetch.
 const INITIAL_REFRESH_OFFSET: Duration = Duration::from_secs(999);
+/// Wait before the first retry when the gateway can't be reached; doubles
+/// with every failure up to `STARTUP_RETRY_MAX`.
+const STARTUP_RETRY_MIN: Duration = Duration::from_secs(2);
+const STARTUP_RETRY_MAX: Duration = Duration::from_secs(60);
+/// After Observe gave up, how long to poll before subscribing again.
+const OBSERVE_RETRY: Duration = Duration::from_secs(300);
 
 // ── Startup result ──────────────────────────────────────
 
 enum StartupResult {
-    Connected { client: SharedTradfriClient, lights: Vec<Light> },
-    Failed(String),
+    Connected { client: SharedTradfriClient, snapshot: Snapshot },
+    /// The attempt failed; the next one starts after `retry_in`.
+    Failed { error: String, retry_in: Duration },
+}
+
+/// Connect and fetch until it works, backing off between attempts. A message
+/// on `wake` skips the rest of the current wait; the thread ends once
+/// connected or when the app is gone.
+fn connect_with_retry(backend: Backend, tx: mpsc::Sender<StartupResult>, wake: mpsc::Receiver<()>) {
+    let mut retry_in = STARTUP_RETRY_MIN;
+    loop {
+        // One operation: connect, fetch devices pipelined, reuse connection as client.
+        let error = match coap::connect_and_fetch(&backend) {
+            Ok((snapshot, client)) => {
+                let snapshot = Snapshot::from(snapshot);
+                let _ = tx.send(StartupResult::Connected { client, snapshot });
+                return;
+            }
+            Err(e) => e,
+        };
+        tracing::warn!("gateway unreachable, retrying in {}s: {:#}", retry_in.as_secs(), error);
+        if tx.send(StartupResult::Failed { error: error.to_string(), retry_in }).is_err() {
+            return;
+        }
+        if let Err(mpsc::RecvTimeoutError::Disconnected) = wake.recv_timeout(retry_in) {
+            return;
+        }
+        retry_in = (retry_in * 2).min(STARTUP_RETRY_MAX);
+    }
+}
+
+/// Which list the TUI shows (Tab switches).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum View {
+    Lights,
+    Rooms,
+    /// Only reachable when the gateway has blinds.
+    Blinds,
+    /// Devices whose battery is at or below `ui.battery_warning`.
+    Batteries,
+}
+
+/// What an inline rename applies to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RenameTarget {
+    /// A light, plug or blind (`15001`)
+    Device(u64),
+    /// A room (`15004`)
+    Group(u64),
+}
+
+/// Inline rename in progress (`N`): the text typed so far.
+#[derive(Debug, Clone)]
+pub struct RenameEdit {
+    pub target: RenameTarget,
+    pub original: String,
+    pub input: String,
+}
+
+/// A question awaiting j/n before FrostLux acts on it.
+#[derive(Debug, Clone)]
+pub enum Confirm {
+    /// After a rename: point scene config entries at the new name.
+    UpdateSceneNames { old: String, new: String },
+    /// Restart the gateway (`15011/9030`).
+    RebootGateway,
+    /// Drop identities from FrostLux's record of paired identities.
+    ForgetIdentities { names: Vec<String> },
 }
 
 // ── App State ───────────────────────────────────────────
 
 pub struct App {
     pub config: Config,
+    /// Where sessions come from: the configured gateway, or `--demo`.
+    backend: Backend,
     pub client: Option<SharedTradfriClient>,
+    /// Every paired device, for the Batteries view.
+    pub devices: Vec<Device>,
     pub lights: Vec<Light>,
+    pub blinds: Vec<Blind>,
+    pub groups: Vec<Group>,
+    pub moods: Vec<Mood>,
+    /// The gateway's own health, fetched after connecting and when its
+    /// popup opens.
+    pub gateway: Option<Gateway>,
+    /// Identities paired from this machine, read when the gateway popup opens.
+    pub identities: Vec<PairedIdentity>,
+    pub view: View,
     pub selected: usize,
+    pub selected_group: usize,
+    pub selected_blind: usize,
     pub should_quit: bool,
     pub status_msg: Option<(String, Instant)>,
     pub last_refresh: Instant,
     pub show_help: bool,
+    /// Detail pane for the selected light or blind.
+    pub show_details: bool,
+    /// Gateway info popup.
+    pub show_gateway: bool,
+    /// Selected row while the scene picker popup is open.
+    pub scene_picker: Option<usize>,
     pub is_connecting: bool,
-    refresh_tx: mpsc::Sender<Vec<Light>>,
-    refresh_rx: mpsc::Receiver<Vec<Light>>,
+    /// Set while the gateway can't be reached: last error and when the next
+    /// connection attempt starts.
+    pub offline: Option<(String, Instant)>,
+    /// True while the gateway pushes state changes via CoAP Observe; periodic
+    /// polling only runs when this is false.
+    pub is_observing: bool,
+    /// Inline rename of the selected light, blind or room.
+    pub rename: Option<RenameEdit>,
+    /// Question shown until answered with j/n.
+    pub confirm: Option<Confirm>,
+    /// Slow fade in progress, if any.
+    pub fade: Option<Arc<Fade>>,
+    refresh_tx: mpsc::Sender<Snapshot>,
+    refresh_rx: mpsc::Receiver<Snapshot>,
+    gateway_tx: mpsc::Sender<Gateway>,
+    gateway_rx: mpsc::Receiver<Gateway>,
     startup_rx: Option<mpsc::Receiver<StartupResult>>,
+    /// Wakes the startup thread for an immediate retry.
+    retry_tx: Option<mpsc::Sender<()>>,
+    observe_rx: Option<mpsc::Receiver<ObserveEvent>>,
+    /// The running subscription; replacing or dropping it closes its session.
+    observer: Option<coap::Observer>,
+    /// Devices the current observe subscription covers.
+    observed_ids: Vec<u64>,
+    /// When to subscribe again after Observe gave up.
+    observe_retry_at: Option<Instant>,
 }
 
 impl App {
-    /// Create app and immediately kick off a background connection + parallel
-    /// light fetch. The TUI is shown instantly; lights appear when ready.
-    pub fn new(config: Config) -> Self {
+    /// Create app and immediately kick off a background connection + pipelined
+    /// light fetch. The TUI is shown instantly; lights appear when ready. If
+    /// the gateway can't be reached, connecting is retried with backoff.
+    pub fn new(config: Config, backend: Backend) -> Self {
         let (refresh_tx, refresh_rx) = mpsc::channel();
+        let (gateway_tx, gateway_rx) = mpsc::channel();
         let (startup_tx, startup_rx) = mpsc::channel::<StartupResult>();
+        let (retry_tx, retry_rx) = mpsc::channel();
 
-        let host = config.gateway.host.clone();
-        let identity = config.gateway.identity.clone();
-        let psk = config.gateway.psk.clone();
-
-        std::thread::spawn(move || {
-            // One operation: connect, fetch lights in parallel, reuse connection as client.
-            match coap::connect_and_fetch_lights(&host, &identity, &psk) {
-                Ok((infos, client)) => {
-                    let mut lights: Vec<Light> = infos.into_iter().map(Light::from).collect();
-                    lights.sort_by(|a, b| a.name.cmp(&b.name));
-                    let _ = startup_tx.send(StartupResult::Connected { client, lights });
-                }
-                Err(e) => {
-                    let _ = startup_tx.send(StartupResult::Failed(e.to_string()));
-                }
-            }
-        });
+        let startup_backend = backend.clone();
+        std::thread::spawn(move || connect_with_retry(startup_backend, startup_tx, retry_rx));
 
         Self {
             config,
+            backend,
             client: None,
+            devices: Vec::new(),
             lights: Vec::new(),
+            blinds: Vec::new(),
+            groups: Vec::new(),
+            moods: Vec::new(),
+            gateway: None,
+            identities: Vec::new(),
+            view: View::Lights,
             selected: 0,
+            selected_group: 0,
+            selected_blind: 0,
             should_quit: false,
             status_msg: None,
             // Subtract INITIAL_REFRESH_OFFSET so the first background refresh
             // fires immediately once a client is connected.
             last_refresh: Instant::now() - INITIAL_REFRESH_OFFSET,
             show_help: false,
+            show_details: false,
+            show_gateway: false,
+            scene_picker: None,
             is_connecting: true,
+            offline: None,
+            is_observing: false,
+            rename: None,
+            confirm: None,
+            fade: None,
             refresh_tx,
             refresh_rx,
+            gateway_tx,
+            gateway_rx,
             startup_rx: Some(startup_rx),
+            retry_tx: Some(retry_tx),
+            observe_rx: None,
+            observer: None,
+            observed_ids: Vec::new(),
+            observe_retry_at: None,
         }
     }
 
@@ -99,13 +234,182 @@ impl App {
     }
 
     pub fn select_next(&mut self) {
-        if !self.lights.is_empty() {
-            self.selected = (self.selected + 1).min(self.lights.len() - 1);
+        match self.view {
+            View::Lights => {
+                if !self.lights.is_empty() {
+                    self.selected = (self.selected + 1).min(self.lights.len() - 1);
+                }
+            }
+            View::Rooms => {
+                if !self.groups.is_empty() {
+                    self.selected_group = (self.selected_group + 1).min(self.groups.len() - 1);
+                }
+            }
+            View::Blinds => {
+                if !self.blinds.is_empty() {
+                    self.selected_blind = (self.selected_blind + 1).min(self.blinds.len() - 1);
+                }
+            }
+            View::Batteries => {}
         }
     }
 
     pub fn select_prev(&mut self) {
-        self.selected = self.selected.saturating_sub(1);
+        match self.view {
+            View::Lights => self.selected = self.selected.saturating_sub(1),
+            View::Rooms => self.selected_group = self.selected_group.saturating_sub(1),
+            View::Blinds => self.selected_blind = self.selected_blind.saturating_sub(1),
+            View::Batteries => {}
+        }
+    }
+
+    pub fn toggle_view(&mut self) {
+        self.view = match self.view {
+            View::Lights => View::Rooms,
+            View::Rooms if !self.blinds.is_empty() => View::Blinds,
+            View::Rooms | View::Blinds => View::Batteries,
+            View::Batteries => View::Lights,
+        };
+    }
+
+    pub fn selected_group(&self) -> Option<&Group> {
+        self.groups.get(self.selected_group)
+    }
+
+    /// Device info for the selected light or blind (Lights / Blinds view).
+    pub fn selected_device(&self) -> Option<&Device> {
+        let id = match self.view {
+            View::Lights => self.lights.get(self.selected)?.id,
+            View::Blinds => self.blinds.get(self.selected_blind)?.id,
+            View::Rooms | View::Batteries => return None,
+        };
+        self.devices.iter().find(|d| d.id == id)
+    }
+
+    /// The room scenes are scoped to: the selected group in the Rooms view.
+    fn scene_room(&self) -> Option<Group> {
+        match self.view {
+            View::Rooms => self.selected_group().cloned(),
+            View::Lights | View::Blinds | View::Batteries => None,
+        }
+    }
+
+    /// Battery-powered devices at or below `ui.battery_warning`, emptiest first.
+    pub fn low_battery_devices(&self) -> Vec<&Device> {
+        let threshold = self.config.ui.battery_warning;
+        let mut low: Vec<&Device> = self
+            .devices
+            .iter()
+            .filter(|d| d.battery.is_some_and(|b| b <= threshold))
+            .collect();
+        low.sort_by_key(|d| d.battery);
+        low
+    }
+
+    /// Lights that are members of `group`.
+    pub fn group_lights<'a>(&'a self, group: &'a Group) -> impl Iterator<Item = &'a Light> {
+        self.lights.iter().filter(|l| group.member_ids.contains(&l.id))
+    }
+
+    pub fn group_name(&self, id: u64) -> &str {
+        self.groups.iter().find(|g| g.id == id).map_or("?", |g| g.name.as_str())
+    }
+
+    /// Built-in scenes followed by gateway moods (only the selected room's
+    /// moods in the Rooms view).
+    pub fn scene_choices(&self) -> Vec<SceneChoice> {
+        let room = self.scene_room().map(|g| g.id);
+        Scene::all()
+            .iter()
+            .map(|&s| SceneChoice::Builtin(s))
+            .chain(
+                self.moods
+                    .iter()
+                    .filter(|m| room.is_none_or(|id| m.group_id == id))
+                    .cloned()
+                    .map(SceneChoice::Mood),
+            )
+            .collect()
+    }
+
+    pub fn open_scene_picker(&mut self) {
+        self.scene_picker = Some(0);
+    }
+
+    pub fn scene_picker_move(&mut self, delta: isize) {
+        let len = self.scene_choices().len();
+        if let Some(i) = self.scene_picker.as_mut() {
+            *i = i.saturating_add_signed(delta).min(len.saturating_sub(1));
+        }
+    }
+
+    /// Apply the highlighted picker entry and close the picker.
+    pub fn confirm_scene_picker(&mut self) -> Result<()> {
+        let Some(i) = self.scene_picker.take() else { return Ok(()) };
+        match self.scene_choices().get(i).cloned() {
+            Some(SceneChoice::Builtin(scene)) => self.apply_scene(scene),
+            Some(SceneChoice::Mood(mood)) => self.activate_mood(mood),
+            None => Ok(()),
+        }
+    }
+
+    /// Like `confirm_scene_picker`, but slowly fades to the scene.
+    pub fn fade_scene_picker(&mut self) -> Result<()> {
+        let Some(i) = self.scene_picker.take() else { return Ok(()) };
+        match self.scene_choices().get(i).cloned() {
+            Some(SceneChoice::Builtin(scene)) => self.start_fade(scene),
+            Some(SceneChoice::Mood(mood)) => {
+                self.set_status(&format!("{}: gateway moods can't fade", mood.name));
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Activate a gateway mood. The resulting light levels arrive via observe.
+    pub fn activate_mood(&mut self, mood: Mood) -> Result<()> {
+        let Some(client) = self.client.clone() else {
+            self.set_status("Väntar på anslutning...");
+            return Ok(());
+        };
+        let members: Vec<u64> = self
+            .groups
+            .iter()
+            .find(|g| g.id == mood.group_id)
+            .map(|g| g.member_ids.clone())
+            .unwrap_or_default();
+        self.touch_lights(&members);
+        for light in &mut self.lights {
+            if members.contains(&light.id) {
+                light.on = true;
+            }
+        }
+        if let Some(group) = self.groups.iter_mut().find(|g| g.id == mood.group_id) {
+            group.mood_id = Some(mood.id);
+        }
+        let group_name = self.group_name(mood.group_id).to_string();
+        self.set_status(&format!("Stämning: {} ({})", mood.name, group_name));
+        let transition = self.config.scenes.transition();
+        std::thread::spawn(move || {
+            if let Err(e) = tradfri::activate_mood(&client, &mood, transition) {
+                tracing::warn!("activate_mood failed for '{}': {}", mood.name, e);
+            }
+        });
+        Ok(())
+    }
+
+    /// Seconds until the next connection attempt while offline.
+    pub fn offline_retry_in(&self) -> Option<u64> {
+        let (_, retry_at) = self.offline.as_ref()?;
+        Some(retry_at.saturating_duration_since(Instant::now()).as_secs())
+    }
+
+    /// Retry connecting now instead of waiting out the backoff.
+    pub fn retry_connect(&mut self) {
+        let Some(tx) = &self.retry_tx else { return };
+        if self.offline.take().is_some() && tx.send(()).is_ok() {
+            self.is_connecting = true;
+        }
     }
 
     /// Start a non-blocking background refresh (only when connected).
@@ -114,43 +418,329 @@ impl App {
         self.last_refresh = Instant::now();
         let tx = self.refresh_tx.clone();
         std::thread::spawn(move || {
-            if let Ok(lights) = tradfri::fetch_lights(&client) {
-                let _ = tx.send(lights);
+            if let Ok(snapshot) = tradfri::fetch_snapshot(&client) {
+                let _ = tx.send(snapshot);
+            }
+        });
+    }
+
+    /// Start editing the name of the selected light, blind or room.
+    pub fn start_rename(&mut self) {
+        let target = match self.view {
+            View::Lights => self.lights.get(self.selected).map(|l| (RenameTarget::Device(l.id), l.name.clone())),
+            View::Blinds => self.blinds.get(self.selected_blind).map(|b| (RenameTarget::Device(b.id), b.name.clone())),
+            View::Rooms => self.selected_group().map(|g| (RenameTarget::Group(g.id), g.name.clone())),
+            View::Batteries => None,
+        };
+        if let Some((target, name)) = target {
+            self.rename = Some(RenameEdit { target, original: name.clone(), input: name });
+        }
+    }
+
+    /// Send the edited name to the gateway, then refresh so lists re-sort.
+    /// Scene config entries naming the device or room are offered for update.
+    pub fn confirm_rename(&mut self) {
+        let Some(edit) = self.rename.take() else { return };
+        let name = edit.input.trim().to_string();
+        if name.is_empty() || name == edit.original {
+            return;
+        }
+        let Some(client) = self.client.clone() else {
+            self.set_status("Väntar på anslutning...");
+            return;
+        };
+
+        match edit.target {
+            RenameTarget::Device(id) => {
+                for light in self.lights.iter_mut().filter(|l| l.id == id) {
+                    light.name = name.clone();
+                }
+                for blind in self.blinds.iter_mut().filter(|b| b.id == id) {
+                    blind.name = name.clone();
+                }
+                for device in self.devices.iter_mut().filter(|d| d.id == id) {
+                    device.name = name.clone();
+                }
+            }
+            RenameTarget::Group(id) => {
+                for group in self.groups.iter_mut().filter(|g| g.id == id) {
+                    group.name = name.clone();
+                }
+            }
+        }
+        self.set_status(&format!("{} → {}", edit.original, name));
+
+        let tx = self.refresh_tx.clone();
+        let target = edit.target;
+        let new_name = name.clone();
+        std::thread::spawn(move || {
+            let result = match target {
+                RenameTarget::Device(id) => client.rename_device(id, &new_name),
+                RenameTarget::Group(id) => client.rename_group(id, &new_name),
+            };
+            if let Err(e) = result {
+                tracing::warn!("rename to '{}' failed: {}", new_name, e);
+            }
+            // Either way the refresh shows what the gateway now calls it.
+            if let Ok(snapshot) = tradfri::fetch_snapshot(&client) {
+                let _ = tx.send(snapshot);
+            }
+        });
+
+        if self.config.scenes.name_references(&edit.original) > 0 {
+            self.confirm = Some(Confirm::UpdateSceneNames { old: edit.original, new: name });
+        }
+    }
+
+    /// Question shown while `confirm` is set.
+    pub fn confirm_text(&self) -> Option<String> {
+        match self.confirm.as_ref()? {
+            Confirm::UpdateSceneNames { old, .. } => {
+                let count = self.config.scenes.name_references(old);
+                Some(format!("Uppdatera {} scenundantag för '{}'? (j/n)", count, old))
+            }
+            Confirm::RebootGateway => Some("Starta om gatewayen? (j/n)".to_string()),
+            Confirm::ForgetIdentities { names } => Some(format!(
+                "Glöm {} oanvända identiteter? Gatewayen godtar dem tills fabriksåterställning. (j/n)",
+                names.len()
+            )),
+        }
+    }
+
+    /// Act on the pending question if `yes`; either way it is dismissed.
+    pub fn answer_confirm(&mut self, yes: bool) {
+        let Some(confirm) = self.confirm.take() else { return };
+        if !yes {
+            return;
+        }
+        match confirm {
+            Confirm::UpdateSceneNames { old, new } => match rename_in_scenes_config(&old, &new) {
+                Ok((count, skipped)) => {
+                    self.config.scenes.rename(&old, &new);
+                    let mut status = format!("Config: {} undantag uppdaterade", count);
+                    if skipped > 0 {
+                        status.push_str(&format!(", {} kvar att ändra för hand", skipped));
+                    }
+                    self.set_status(&status);
+                }
+                Err(e) => self.set_status(&format!("Config ej uppdaterad: {}", e)),
+            },
+            Confirm::RebootGateway => self.reboot_gateway(),
+            Confirm::ForgetIdentities { names } => match forget_identities(&names) {
+                Ok(count) => {
+                    self.identities.retain(|i| !names.contains(&i.name));
+                    self.set_status(&format!("Glömde {} identiteter", count));
+                }
+                Err(e) => self.set_status(&format!("Identiteter ej ändrade: {}", e)),
+            },
+        }
+    }
+
+    /// Ask before restarting the gateway.
+    pub fn request_reboot(&mut self) {
+        if self.client.is_none() {
+            self.set_status("Väntar på anslutning...");
+            return;
+        }
+        self.confirm = Some(Confirm::RebootGateway);
+    }
+
+    /// Restart the gateway. Lights keep their state; the session and the
+    /// observer reconnect by themselves once it is back (about a minute).
+    fn reboot_gateway(&mut self) {
+        let Some(client) = self.client.clone() else { return };
+        self.show_gateway = false;
+        self.gateway = None;
+        self.set_status("Gatewayen startar om...");
+        std::thread::spawn(move || {
+            if let Err(e) = client.reboot_gateway() {
+                tracing::warn!("gateway reboot failed: {}", e);
+            }
+        });
+    }
+
+    /// Fetch the gateway's own details in the background.
+    pub fn start_gateway_fetch(&mut self) {
+        let Some(client) = self.client.clone() else { return };
+        let tx = self.gateway_tx.clone();
+        std::thread::spawn(move || match tradfri::fetch_gateway(&client) {
+            Ok(gateway) => {
+                let _ = tx.send(gateway);
             }
+            Err(e) => tracing::warn!("gateway info failed: {}", e),
         });
     }
 
+    /// Open the gateway popup with fresh details.
+    pub fn open_gateway_info(&mut self) {
+        if self.client.is_none() {
+            self.set_status("Väntar på anslutning...");
+            return;
+        }
+        self.show_gateway = true;
+        self.start_gateway_fetch();
+        // The record belongs to the real gateway, not to `--demo`.
+        if matches!(self.backend, Backend::Dtls { .. }) {
+            self.identities = load_identities().unwrap_or_else(|e| {
+                tracing::warn!("paired identities unreadable: {:#}", e);
+                Vec::new()
+            });
+        }
+    }
+
+    /// Recorded identities other than the one FrostLux connects with.
+    pub fn unused_identities(&self) -> Vec<String> {
+        self.identities
+            .iter()
+            .filter(|i| i.name != self.config.gateway.identity)
+            .map(|i| i.name.clone())
+            .collect()
+    }
+
+    /// Ask before forgetting every unused identity.
+    pub fn request_forget_identities(&mut self) {
+        let names = self.unused_identities();
+        if names.is_empty() {
+            self.set_status("Inga oanvända identiteter");
+            return;
+        }
+        self.confirm = Some(Confirm::ForgetIdentities { names });
+    }
+
     /// Poll for startup result and periodic refresh results.
     pub fn poll_refresh(&mut self) {
         // Handle initial connection result.
         if let Some(rx) = &self.startup_rx {
             if let Ok(result) = rx.try_recv() {
                 match result {
-                    StartupResult::Connected { client, lights } => {
+                    StartupResult::Connected { client, snapshot } => {
                         self.client = Some(client);
-                        self.lights = lights;
+                        self.apply_snapshot(snapshot);
                         self.is_connecting = false;
-                        self.last_refresh = Instant::now();
-                        if self.selected >= self.lights.len() {
-                            self.selected = self.lights.len().saturating_sub(1);
+                        if self.offline.take().is_some() {
+                            self.set_status("Ansluten till gateway");
                         }
+                        self.last_refresh = Instant::now();
+                        self.start_observing();
+                        self.start_gateway_fetch();
+                        self.startup_rx = None;
+                        self.retry_tx = None;
                     }
-                    StartupResult::Failed(err) => {
+                    StartupResult::Failed { error, retry_in } => {
                         self.is_connecting = false;
-                        self.set_status(&format!("Anslutning misslyckades: {}", err));
+                        self.offline = Some((error, Instant::now() + retry_in));
+                    }
+                }
+            }
+        }
+
+        // Handle periodic background refresh. The gateway answering again
+        // after Observe gave up (e.g. it rebooted) is a cue to re-subscribe,
+        // as are lights and blinds paired since the subscription started.
+        if let Ok(snapshot) = self.refresh_rx.try_recv() {
+            self.apply_snapshot(snapshot);
+            if self.observe_retry_at.is_some_and(|at| Instant::now() >= at) {
+                self.observe_retry_at = None;
+                self.start_observing();
+            } else if self.is_observing && self.observable_ids().iter().any(|id| !self.observed_ids.contains(id)) {
+                tracing::info!("new devices found, re-subscribing");
+                self.start_observing();
+            }
+        }
+
+        if let Ok(gateway) = self.gateway_rx.try_recv() {
+            for warning in gateway.warnings() {
+                tracing::warn!("gateway: {}", warning);
+            }
+            self.gateway = Some(gateway);
+        }
+
+        // Apply state pushed by the gateway.
+        let mut ended = None;
+        if let Some(rx) = &self.observe_rx {
+            while let Ok(event) = rx.try_recv() {
+                match event {
+                    ObserveEvent::Light(info) => {
+                        let light = Light::from(info);
+                        if let Some(name) = self.fade.as_ref().and_then(|f| f.observe(&light)) {
+                            self.status_msg = Some((format!("Fade avbruten: {}", name), Instant::now()));
+                        }
+                        match self.lights.iter_mut().find(|l| l.id == light.id) {
+                            Some(existing) => *existing = light,
+                            None => self.lights.push(light),
+                        }
                     }
+                    ObserveEvent::Blind(info) => {
+                        let blind = Blind::from(info);
+                        if let Some(device) = self.devices.iter_mut().find(|d| d.id == blind.id) {
+                            device.battery = blind.battery;
+                        }
+                        match self.blinds.iter_mut().find(|b| b.id == blind.id) {
+                            Some(existing) => *existing = blind,
+                            None => self.blinds.push(blind),
+                        }
+                    }
+                    ObserveEvent::Ended(err) => ended = Some(err),
                 }
-                self.startup_rx = None;
             }
         }
+        if let Some(err) = ended {
+            tracing::warn!("observe ended, falling back to polling: {}", err);
+            self.observe_rx = None;
+            self.observer = None;
+            self.is_observing = false;
+            self.observe_retry_at = Some(Instant::now() + OBSERVE_RETRY);
+        }
+
+        if self.fade.as_ref().is_some_and(|f| !f.is_running()) {
+            self.fade = None;
+        }
+    }
 
-        // Handle periodic background refresh.
-        if let Ok(lights) = self.refresh_rx.try_recv() {
-            self.lights = lights;
-            if self.selected >= self.lights.len() {
-                self.selected = self.lights.len().saturating_sub(1);
+    fn apply_snapshot(&mut self, snapshot: Snapshot) {
+        if let Some(fade) = &self.fade {
+            for light in &snapshot.lights {
+                if let Some(name) = fade.observe(light) {
+                    self.status_msg = Some((format!("Fade avbruten: {}", name), Instant::now()));
+                }
             }
         }
+        self.devices = snapshot.devices;
+        self.lights = snapshot.lights;
+        self.blinds = snapshot.blinds;
+        self.groups = snapshot.groups;
+        self.moods = snapshot.moods;
+        if self.selected >= self.lights.len() {
+            self.selected = self.lights.len().saturating_sub(1);
+        }
+        if self.selected_group >= self.groups.len() {
+            self.selected_group = self.groups.len().saturating_sub(1);
+        }
+        if self.selected_blind >= self.blinds.len() {
+            self.selected_blind = self.blinds.len().saturating_sub(1);
+        }
+        if self.view == View::Blinds && self.blinds.is_empty() {
+            self.view = View::Lights;
+        }
+    }
+
+    /// Every known light and blind: what an observe subscription covers.
+    fn observable_ids(&self) -> Vec<u64> {
+        self.lights
+            .iter()
+            .map(|l| l.id)
+            .chain(self.blinds.iter().map(|b| b.id))
+            .collect()
+    }
+
+    /// Subscribe to push updates for every known light and blind. A previous
+    /// subscription is stopped and its session closed.
+    fn start_observing(&mut self) {
+        let (tx, rx) = mpsc::channel();
+        self.observed_ids = self.observable_ids();
+        self.observer = Some(coap::spawn_observer(self.backend.clone(), self.observed_ids.clone(), tx));
+        self.observe_rx = Some(rx);
+        self.is_observing = true;
     }
 
     pub fn toggle_selected(&mut self) -> Result<()> {
@@ -158,14 +748,22 @@ impl App {
             self.set_status("Väntar på anslutning...");
             return Ok(());
         };
+        match self.view {
+            View::Rooms => return self.toggle_selected_group(client),
+            View::Blinds => return self.stop_selected_blind(client),
+            View::Batteries => return Ok(()),
+            View::Lights => {}
+        }
         if let Some(light) = self.lights.get(self.selected).cloned() {
+            self.touch_lights(&[light.id]);
             let new_state = !light.on;
             if let Some(l) = self.lights.get_mut(self.selected) {
                 l.on = new_state;
             }
             self.set_status(&format!("{}: {}", light.name, if new_state { "ON" } else { "OFF" }));
+            let transition = self.config.ui.transition();
             std::thread::spawn(move || {
-                if let Err(e) = tradfri::set_power(&client, light.id, new_state) {
+                if let Err(e) = tradfri::set_power(&client, &light, new_state, transition) {
                     tracing::warn!("set_power failed for '{}': {}", light.name, e);
                 }
             });
@@ -178,7 +776,18 @@ impl App {
             self.set_status("Väntar på anslutning...");
             return Ok(());
         };
+        match self.view {
+            View::Rooms => return self.dim_selected_group(client, delta),
+            View::Blinds => return self.move_selected_blind(client, delta),
+            View::Batteries => return Ok(()),
+            View::Lights => {}
+        }
         if let Some(light) = self.lights.get(self.selected).cloned() {
+            if light.plug {
+                self.set_status(&format!("{}: uttag, bara på/av", light.name));
+                return Ok(());
+            }
+            self.touch_lights(&[light.id]);
             let new_brightness = (light.brightness as i16 + delta).clamp(0, 254) as u8;
             if let Some(l) = self.lights.get_mut(self.selected) {
                 l.brightness = new_brightness;
@@ -186,8 +795,9 @@ impl App {
             }
             let pct = ((new_brightness as f32 / 254.0) * 100.0).round() as u8;
             self.set_status(&format!("{}: {}%", light.name, pct));
+            let transition = self.config.ui.transition();
             std::thread::spawn(move || {
-                if let Err(e) = tradfri::set_brightness(&client, &light, new_brightness) {
+                if let Err(e) = tradfri::set_brightness(&client, &light, new_brightness, transition) {
                     tracing::warn!("set_brightness failed for '{}': {}", light.name, e);
                 }
             });
@@ -200,7 +810,39 @@ impl App {
             self.set_status("Väntar på anslutning...");
             return Ok(());
         };
-        if let Some(light) = self.lights.get(self.selected).cloned() {
+        if self.view != View::Lights {
+            self.set_status("Färg: välj en enskild lampa (Tab)");
+            return Ok(());
+        }
+        let Some(light) = self.lights.get(self.selected).cloned() else { return Ok(()) };
+        if light.plug {
+            self.set_status(&format!("{}: uttag, bara på/av", light.name));
+            return Ok(());
+        }
+        self.touch_lights(&[light.id]);
+        if let Some((min, max)) = light.mireds_range() {
+            // Continuous: higher mireds = warmer.
+            let current = light.mireds.unwrap_or(min);
+            let mireds = if warmer {
+                current.saturating_add(MIREDS_STEP).min(max)
+            } else {
+                current.saturating_sub(MIREDS_STEP).max(min)
+            };
+            let kelvin = color::mireds_to_kelvin(mireds);
+            if let Some(l) = self.lights.get_mut(self.selected) {
+                l.mireds = Some(mireds);
+            }
+            self.set_status(&format!("{}: {}K", light.name, kelvin));
+            let transition = self.config.ui.transition();
+            std::thread::spawn(move || {
+                if let Err(e) = tradfri::set_kelvin(&client, &light, kelvin, transition) {
+                    tracing::warn!("set_kelvin failed for '{}': {}", light.name, e);
+                }
+            });
+            return Ok(());
+        }
+        // Bulbs that don't report mireds only know the three hex presets.
+        {
             let temps = COLOR_TEMPS;
             let labels = COLOR_TEMP_LABELS;
             let current_idx = temps.iter().position(|&h| Some(h) == light.color_hex.as_deref());
@@ -215,8 +857,9 @@ impl App {
             }
             self.set_status(&format!("{}: {}", light.name, labels[new_idx]));
             let hex = temps[new_idx].to_string();
+            let transition = self.config.ui.transition();
             std::thread::spawn(move || {
-                if let Err(e) = tradfri::set_color_temp(&client, &light, &hex) {
+                if let Err(e) = tradfri::set_color_temp(&client, &light, &hex, transition) {
                     tracing::warn!("set_color_temp failed for '{}': {}", light.name, e);
                 }
             });
@@ -224,59 +867,360 @@ impl App {
         Ok(())
     }
 
-    /// Apply a scene to all non-excluded lights.
-    pub fn apply_scene(&mut self, scene: Scene) -> Result<()> {
+    /// Step a colour bulb through `COLOR_PALETTE`.
+    pub fn cycle_color(&mut self, forward: bool) -> Result<()> {
         let Some(client) = self.client.clone() else {
             self.set_status("Väntar på anslutning...");
             return Ok(());
         };
-        let (on, brightness, color) = scene.settings();
-        let scenes_cfg = &self.config.scenes;
-        let targets: Vec<u64> = self.lights.iter()
-            .filter(|l| !scenes_cfg.is_excluded_for_scene(scene, &l.name))
-            .map(|l| l.id)
-            .collect();
+        if self.view != View::Lights {
+            self.set_status("Färg: välj en enskild lampa (Tab)");
+            return Ok(());
+        }
+        let Some(light) = self.lights.get(self.selected).cloned() else { return Ok(()) };
+        if !light.is_color() {
+            self.set_status(&format!("{}: not a colour bulb", light.name));
+            return Ok(());
+        }
+        self.touch_lights(&[light.id]);
+        let len = COLOR_PALETTE.len();
+        let new_idx = match (light.palette_index(), forward) {
+            (Some(i), true) => (i + 1) % len,
+            (Some(i), false) => (i + len - 1) % len,
+            (None, _) => 0,
+        };
+        let (name, rgb) = COLOR_PALETTE[new_idx];
+        if let Some(l) = self.lights.get_mut(self.selected) {
+            let (x, y) = color::rgb_to_xy(rgb.0, rgb.1, rgb.2);
+            l.color_xy = Some(color::xy_to_raw(x, y));
+        }
+        self.set_status(&format!("{}: {}", light.name, name));
+        let transition = self.config.ui.transition();
+        std::thread::spawn(move || {
+            if let Err(e) = tradfri::set_rgb(&client, &light, rgb, transition) {
+                tracing::warn!("set_rgb failed for '{}': {}", light.name, e);
+            }
+        });
+        Ok(())
+    }
+
+    fn toggle_selected_group(&mut self, client: SharedTradfriClient) -> Result<()> {
+        let Some(group) = self.selected_group().cloned() else { return Ok(()) };
+        self.touch_lights(&group.member_ids);
+        let new_state = !self.group_lights(&group).any(|l| l.on);
         for light in &mut self.lights {
-            if !scenes_cfg.is_excluded_for_scene(scene, &light.name) {
-                light.on = on;
-                if on {
-                    light.brightness = brightness;
-                    light.color_hex = Some(color.to_string());
-                }
+            if group.member_ids.contains(&light.id) {
+                light.on = new_state;
+            }
+        }
+        self.set_status(&format!("{}: {}", group.name, if new_state { "ON" } else { "OFF" }));
+        let transition = self.config.ui.transition();
+        std::thread::spawn(move || {
+            if let Err(e) = tradfri::set_group_power(&client, &group, new_state, transition) {
+                tracing::warn!("set_group_power failed for '{}': {}", group.name, e);
+            }
+        });
+        Ok(())
+    }
+
+    fn dim_selected_group(&mut self, client: SharedTradfriClient, delta: i16) -> Result<()> {
+        let Some(group) = self.selected_group().cloned() else { return Ok(()) };
+        self.touch_lights(&group.member_ids);
+        let current = self
+            .group_lights(&group)
+            .filter(|l| l.on)
+            .map(|l| l.brightness)
+            .max()
+            .unwrap_or(0);
+        let new_brightness = (current as i16 + delta).clamp(0, 254) as u8;
+        for light in &mut self.lights {
+            if group.member_ids.contains(&light.id) {
+                light.brightness = new_brightness;
+                light.on = new_brightness > 0;
+            }
+        }
+        let pct = ((new_brightness as f32 / 254.0) * 100.0).round() as u8;
+        self.set_status(&format!("{}: {}%", group.name, pct));
+        let transition = self.config.ui.transition();
+        std::thread::spawn(move || {
+            if let Err(e) = tradfri::set_group_brightness(&client, &group, new_brightness, transition) {
+                tracing::warn!("set_group_brightness failed for '{}': {}", group.name, e);
+            }
+        });
+        Ok(())
+    }
+
+    /// Stop the selected blind where it is.
+    fn stop_selected_blind(&mut self, client: SharedTradfriClient) -> Result<()> {
+        let Some(blind) = self.blinds.get(self.selected_blind).cloned() else { return Ok(()) };
+        self.set_status(&format!("{}: stop", blind.name));
+        std::thread::spawn(move || {
+            if let Err(e) = tradfri::stop_blind(&client, &blind) {
+                tracing::warn!("stop_blind failed for '{}': {}", blind.name, e);
             }
+        });
+        Ok(())
+    }
+
+    /// Raise (`delta` > 0) or lower the selected blind, like dimming a light:
+    /// small steps move 10%, large ones (PgUp/PgDn) all the way.
+    fn move_selected_blind(&mut self, client: SharedTradfriClient, delta: i16) -> Result<()> {
+        let Some(blind) = self.blinds.get(self.selected_blind).cloned() else { return Ok(()) };
+        let step: i16 = if delta.abs() >= 64 { 100 } else { 10 };
+        // Raising opens, i.e. lowers the gateway position.
+        let position = (blind.position as i16 - delta.signum() * step).clamp(0, 100) as u8;
+        if let Some(b) = self.blinds.get_mut(self.selected_blind) {
+            b.position = position;
         }
-        self.set_status(&format!("Scene: {}", scene.name()));
-        let color = color.to_string();
+        self.set_status(&format!("{}: {}% open", blind.name, 100 - position));
         std::thread::spawn(move || {
-            for id in targets {
-                if let Err(e) = client.apply_scene_to_light(id, on, brightness, &color) {
-                    tracing::warn!("apply_scene failed for light id {}: {}", id, e);
+            if let Err(e) = tradfri::set_blind_position(&client, &blind, position) {
+                tracing::warn!("set_blind_position failed for '{}': {}", blind.name, e);
+            }
+        });
+        Ok(())
+    }
+
+    /// Blinds a scene moves, with their target position, limited to `room`.
+    fn scene_blinds(&self, scene: Scene, room: Option<&Group>) -> Vec<(Blind, u8)> {
+        self.blinds
+            .iter()
+            .filter(|b| room.is_none_or(|g| g.member_ids.contains(&b.id)))
+            .filter_map(|b| {
+                let position = self.config.scenes.blind_position(scene, &b.name)?;
+                Some((b.clone(), position))
+            })
+            .collect()
+    }
+
+    /// Apply a scene to all non-excluded lights, or only to the selected
+    /// room's lights in the Rooms view.
+    pub fn apply_scene(&mut self, scene: Scene) -> Result<()> {
+        let Some(client) = self.client.clone() else {
+            self.set_status("Väntar på anslutning...");
+            return Ok(());
+        };
+        let room = self.scene_room();
+        let settings = self.config.scenes.settings_for(scene);
+        let targets = self.scene_targets(scene, room.as_ref());
+        self.touch_lights(&targets);
+        let mut plan = plan_scene(&self.groups, &self.lights, &targets);
+        for (blind, position) in self.scene_blinds(scene, room.as_ref()) {
+            plan.blinds.push((blind.id, position));
+            if let Some(b) = self.blinds.iter_mut().find(|b| b.id == blind.id) {
+                b.position = position;
+            }
+        }
+        for light in &mut self.lights {
+            if targets.contains(&light.id) {
+                light.on = settings.on;
+                if settings.on && !light.plug {
+                    light.brightness = settings.brightness;
+                    if light.mireds.is_some() {
+                        light.mireds = Some(color::kelvin_to_mireds(settings.kelvin));
+                    }
                 }
             }
+        }
+        match &room {
+            Some(group) => self.set_status(&format!("Scene: {} ({})", scene.name(), group.name)),
+            None => self.set_status(&format!("Scene: {}", scene.name())),
+        }
+        std::thread::spawn(move || {
+            apply_scene_plan(&client, &plan, &settings);
         });
         Ok(())
     }
 
-    /// Run a scene in headless mode (no TUI) — for CLI usage.
-    pub fn run_scene_headless(config: &Config, scene: Scene) -> Result<()> {
+    /// Non-excluded lights a scene applies to, limited to `room` if given.
+    fn scene_targets(&self, scene: Scene, room: Option<&Group>) -> Vec<u64> {
+        self.lights
+            .iter()
+            .filter(|l| self.config.scenes.includes(scene, l))
+            .filter(|l| room.is_none_or(|g| g.member_ids.contains(&l.id)))
+            .map(|l| l.id)
+            .collect()
+    }
+
+    /// Slowly fade the scene's lights (room-scoped in the Rooms view) to it
+    /// over `scenes.fade_minutes`. Replaces any fade already running.
+    pub fn start_fade(&mut self, scene: Scene) -> Result<()> {
+        let Some(client) = self.client.clone() else {
+            self.set_status("Väntar på anslutning...");
+            return Ok(());
+        };
+        let room = self.scene_room();
+        let targets = self.scene_targets(scene, room.as_ref());
+        let lights: Vec<&Light> = self.lights.iter().filter(|l| targets.contains(&l.id)).collect();
+        let duration = self.config.scenes.fade_duration();
+        let fade = Fade::new(scene.name(), &lights, &self.config.scenes.settings_for(scene), duration);
+        if let Some(old) = self.fade.replace(Arc::clone(&fade)) {
+            old.cancel();
+        }
+        self.set_status(&format!(
+            "Fade: {} ({} min)",
+            scene.name(),
+            (duration.as_secs_f32() / 60.0).round()
+        ));
+        std::thread::spawn(move || fade.run(&client));
+        Ok(())
+    }
+
+    pub fn cancel_fade(&mut self) {
+        match self.fade.take() {
+            Some(fade) => {
+                fade.cancel();
+                self.set_status(&format!("Fade avbruten: {}", fade.scene_name));
+            }
+            None => self.set_status("Ingen fade pågår"),
+        }
+    }
+
+    /// The user changed these lights by hand: stop fading them.
+    fn touch_lights(&self, ids: &[u64]) {
+        if let Some(fade) = &self.fade {
+            fade.cancel_lights(ids);
+        }
+    }
+
+    /// Run a scene in headless mode (no TUI) — for CLI usage. With `room`,
+    /// only lights in the group of that name are affected.
+    /// With `fade`, lights are faded there slowly instead (blocking until done).
+    pub fn run_scene_headless(
+        config: &Config,
+        scene: Scene,
+        room: Option<&str>,
+        fade: Option<Duration>,
+    ) -> Result<()> {
         use anyhow::Context;
-        let client = SharedTradfriClient::new(
-            &config.gateway.host,
-            &config.gateway.identity,
-            &config.gateway.psk,
-        ).context("Failed to connect to Trådfri gateway")?;
+        let client = SharedTradfriClient::new(&config.gateway.backend())?;
+
+        let settings = config.scenes.settings_for(scene);
+        let lights: Vec<Light> = client.list_lights()?.into_iter().map(Light::from).collect();
+        let groups: Vec<Group> = client.list_groups()?.into_iter().map(Group::from).collect();
+        let room = match room {
+            Some(name) => Some(
+                groups
+                    .iter()
+                    .find(|g| g.name.eq_ignore_ascii_case(name))
+                    .with_context(|| format!("Unknown room: '{}'", name))?,
+            ),
+            None => None,
+        };
+
+        let targets: Vec<u64> = lights
+            .iter()
+            .filter(|l| config.scenes.includes(scene, l))
+            .filter(|l| room.is_none_or(|g| g.member_ids.contains(&l.id)))
+            .map(|l| l.id)
+            .collect();
+        if let Some(duration) = fade {
+            let lights: Vec<Light> = lights
+                .into_iter()
+                .filter(|l| targets.contains(&l.id))
+                .collect();
+            return Self::run_fade_headless(config, client, scene, &lights, &settings, duration);
+        }
+        let mut plan = plan_scene(&groups, &lights, &targets);
+        if config.scenes.moves_blinds(scene) {
+            plan.blinds = client
+                .list_blinds()?
+                .into_iter()
+                .filter(|b| room.is_none_or(|g| g.member_ids.contains(&b.id)))
+                .filter_map(|b| Some((b.id, config.scenes.blind_position(scene, &b.name)?)))
+                .collect();
+        }
+        for &(id, temperature) in &plan.groups {
+            client.apply_scene_to_group(id, settings.state(temperature), settings.transition)?;
+        }
+        for &(id, temperature) in &plan.lights {
+            client.apply_scene_to_light(id, settings.state(temperature), settings.transition)?;
+        }
+        for id in &plan.plugs {
+            client.set_plug_power(*id, settings.on)?;
+        }
+        for &(id, position) in &plan.blinds {
+            client.set_blind_position(id, position)?;
+        }
+        match room {
+            Some(group) => println!("FrostLux: {} applied to {}", scene.name(), group.name),
+            None => println!("FrostLux: {} applied", scene.name()),
+        }
+        Ok(())
+    }
 
-        let (on, brightness, color) = scene.settings();
-        let lights = client.list_lights()?;
-        for light in &lights {
-            if !config.scenes.is_excluded_for_scene(scene, &light.name) {
-                client.apply_scene_to_light(light.id, on, brightness, color)?;
+    /// Fade `lights` to a scene from the command line. Observes the lights
+    /// so any that get changed by hand meanwhile are dropped from the fade.
+    fn run_fade_headless(
+        config: &Config,
+        client: SharedTradfriClient,
+        scene: Scene,
+        lights: &[Light],
+        settings: &SceneSettings,
+        duration: Duration,
+    ) -> Result<()> {
+        let fade = Fade::new(scene.name(), &lights.iter().collect::<Vec<_>>(), settings, duration);
+        let (tx, rx) = mpsc::channel();
+        let _observer = coap::spawn_observer(
+            config.gateway.backend(),
+            lights.iter().map(|l| l.id).collect(),
+            tx,
+        );
+        let runner = {
+            let fade = Arc::clone(&fade);
+            std::thread::spawn(move || fade.run(&client))
+        };
+        println!(
+            "FrostLux: fading to {} over {:.0} min (Ctrl-C to stop)",
+            scene.name(),
+            duration.as_secs_f32() / 60.0
+        );
+
+        while fade.is_running() {
+            match rx.recv_timeout(Duration::from_secs(1)) {
+                Ok(ObserveEvent::Light(info)) => {
+                    if let Some(name) = fade.observe(&Light::from(info)) {
+                        println!("FrostLux: {} changed by hand, leaving it", name);
+                    }
+                }
+                Ok(ObserveEvent::Blind(_) | ObserveEvent::Ended(_))
+                | Err(mpsc::RecvTimeoutError::Timeout) => {}
+                // Without observe, manual changes can't be noticed; just wait.
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    std::thread::sleep(Duration::from_secs(1))
+                }
             }
         }
-        println!("FrostLux: {} applied", scene.name());
+        let _ = runner.join();
+        if fade.active_lights() == 0 {
+            println!("FrostLux: every light was changed by hand, fade stopped");
+        } else {
+            println!("FrostLux: {} reached", scene.name());
+        }
         Ok(())
     }
 
+    /// Activate a gateway mood by name in headless mode. With `room`, only
+    /// moods of the group of that name are considered.
+    pub fn run_mood_headless(config: &Config, name: &str, room: Option<&str>) -> Result<()> {
+        let client = SharedTradfriClient::new(&config.gateway.backend())?;
+
+        let groups = client.list_groups()?;
+        for group in &groups {
+            if room.is_some_and(|r| !group.name.eq_ignore_ascii_case(r)) {
+                continue;
+            }
+            let moods = client.list_moods(group.id)?;
+            if let Some(mood) = moods.iter().find(|m| m.name.eq_ignore_ascii_case(name)) {
+                let transition = config.scenes.transition();
+                client.set_group_mood(group.id, mood.id, transition)?;
+                println!("FrostLux: mood {} applied to {}", mood.name, group.name);
+                return Ok(());
+            }
+        }
+        anyhow::bail!("No gateway mood named '{}'", name)
+    }
+
     pub fn lights_on(&self) -> usize {
         self.lights.iter().filter(|l| l.on).count()
     }
@@ -285,3 +1229,226 @@ impl App {
         self.lights.iter().filter(|l| !l.on).count()
     }
 }
+
+/// Send the requests of a scene plan, logging (not aborting on) failures.
+fn apply_scene_plan(client: &SharedTradfriClient, plan: &ScenePlan, settings: &SceneSettings) {
+    for &(id, temperature) in &plan.groups {
+        if let Err(e) = client.apply_scene_to_group(id, settings.state(temperature), settings.transition) {
+            tracing::warn!("apply_scene failed for group id {}: {}", id, e);
+        }
+    }
+    for &(id, temperature) in &plan.lights {
+        if let Err(e) = client.apply_scene_to_light(id, settings.state(temperature), settings.transition) {
+            tracing::warn!("apply_scene failed for light id {}: {}", id, e);
+        }
+    }
+    for &id in &plan.plugs {
+        if let Err(e) = client.set_plug_power(id, settings.on) {
+            tracing::warn!("apply_scene failed for plug id {}: {}", id, e);
+        }
+    }
+    for &(id, position) in &plan.blinds {
+        if let Err(e) = client.set_blind_position(id, position) {
+            tracing::warn!("apply_scene failed for blind id {}: {}", id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coap::{MemoryGateway, Transport};
+    use serde_json::{json, Value};
+
+    /// Keep checking `done` until it holds, failing after two seconds.
+    fn eventually(what: &str, mut done: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !done() {
+            assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    /// An app connected to the demo home, and the gateway behind it.
+    fn demo_app(config: Config) -> (App, MemoryGateway) {
+        let gateway = MemoryGateway::demo();
+        let mut app = App::new(config, Backend::Memory(gateway.clone()));
+        eventually("connection", || {
+            app.poll_refresh();
+            app.client.is_some()
+        });
+        (app, gateway)
+    }
+
+    fn document(gateway: &MemoryGateway, path: &str) -> Value {
+        serde_json::from_slice(&gateway.clone().get(path).unwrap()).unwrap()
+    }
+
+    fn put(gateway: &MemoryGateway, path: &str, patch: Value) {
+        gateway.clone().put(path, patch.to_string().as_bytes()).unwrap();
+    }
+
+    fn status(app: &App) -> Option<&str> {
+        app.status_msg.as_ref().map(|(msg, _)| msg.as_str())
+    }
+
+    fn light(app: &App, id: u64) -> &Light {
+        app.lights.iter().find(|l| l.id == id).unwrap()
+    }
+
+    #[test]
+    fn scenes_leave_out_plugs_and_move_configured_blinds() {
+        let mut config = Config::default();
+        config.scenes.include_plugs = false;
+        config.scenes.blinds_by_scene.insert("movie".into(), [("Rullgardin".into(), 100)].into());
+        let (mut app, gateway) = demo_app(config);
+
+        let targets = app.scene_targets(Scene::Movie, None);
+        assert_eq!(targets.len(), 5);
+        assert!(!targets.contains(&65542));
+        let blinds = |room: Option<&Group>| -> Vec<(u64, u8)> {
+            app.scene_blinds(Scene::Movie, room).iter().map(|(b, p)| (b.id, *p)).collect()
+        };
+        assert_eq!(blinds(None), [(65543, 100)]);
+        let sovrummet = app.groups.iter().find(|g| g.name == "Sovrummet").unwrap();
+        assert!(blinds(Some(sovrummet)).is_empty());
+
+        app.apply_scene(Scene::Movie).unwrap();
+        // Blinds go last, so the plug would have been switched by now.
+        eventually("blind to close", || document(&gateway, "15001/65543")["15015"][0]["5536"] == 100);
+        assert_eq!(document(&gateway, "15001/65542")["3312"][0]["5850"], 0);
+        assert_eq!(document(&gateway, "15001/65539")["3311"][0]["5850"], 1);
+    }
+
+    #[test]
+    fn observed_changes_update_lights_and_new_devices_are_subscribed() {
+        let (mut app, gateway) = demo_app(Config::default());
+        assert!(app.is_observing);
+
+        put(&gateway, "15001/65539", json!({ "3311": [{ "5850": 1, "5851": 200 }] }));
+        eventually("observed change", || {
+            app.poll_refresh();
+            let tv = light(&app, 65539);
+            tv.on && tv.brightness == 200
+        });
+
+        gateway.add_device(json!({
+            "9003": 65546, "9001": "Hallampa", "5750": 2, "9019": 1,
+            "3": { "1": "TRADFRI bulb E27 W opal 1000lm" },
+            "3311": [{ "5850": 0, "5851": 100 }]
+        }));
+        app.start_background_refresh();
+        eventually("re-subscription", || {
+            app.poll_refresh();
+            app.observed_ids.contains(&65546)
+        });
+        put(&gateway, "15001/65546", json!({ "3311": [{ "5850": 1 }] }));
+        eventually("new light's change", || {
+            app.poll_refresh();
+            light(&app, 65546).on
+        });
+    }
+
+    #[test]
+    fn renaming_a_room_offers_to_update_the_config() {
+        let mut config = Config::default();
+        config.scenes.exclude_by_scene.insert("movie".into(), vec!["Vardagsrum".into()]);
+        let (mut app, gateway) = demo_app(config);
+
+        app.view = View::Rooms;
+        app.selected_group = app.groups.iter().position(|g| g.name == "Vardagsrum").unwrap();
+        app.start_rename();
+        app.rename.as_mut().unwrap().input = "Allrum ".into();
+        app.confirm_rename();
+
+        assert_eq!(app.groups[app.selected_group].name, "Allrum");
+        assert!(matches!(
+            &app.confirm,
+            Some(Confirm::UpdateSceneNames { old, new }) if old == "Vardagsrum" && new == "Allrum"
+        ));
+        assert!(app.confirm_text().unwrap().contains("1 scenundantag för 'Vardagsrum'"));
+        eventually("gateway rename", || document(&gateway, "15004/131073")["9001"] == "Allrum");
+
+        // Declining leaves the config as it was.
+        app.answer_confirm(false);
+        assert!(app.confirm.is_none());
+        assert_eq!(app.config.scenes.name_references("Vardagsrum"), 1);
+    }
+
+    #[test]
+    fn unchanged_or_unreferenced_renames_ask_nothing() {
+        let (mut app, gateway) = demo_app(Config::default());
+
+        app.start_rename();
+        let original = app.rename.as_ref().unwrap().original.clone();
+        app.confirm_rename();
+        assert!(app.rename.is_none());
+        assert!(status(&app).is_none());
+        assert_eq!(app.lights[app.selected].name, original);
+
+        app.selected = app.lights.iter().position(|l| l.id == 65539).unwrap();
+        app.start_rename();
+        app.rename.as_mut().unwrap().input = "Tv-hörnan".into();
+        app.confirm_rename();
+        assert!(app.confirm.is_none());
+        eventually("gateway rename", || document(&gateway, "15001/65539")["9001"] == "Tv-hörnan");
+    }
+
+    #[test]
+    fn forgetting_identities_spares_the_one_in_use() {
+        let mut config = Config::default();
+        config.gateway.identity = "frostlux-b".into();
+        let (mut app, _) = demo_app(config);
+        let identity = |name: &str| PairedIdentity {
+            name: name.into(),
+            host: "192.168.0.10".into(),
+            psk: "key".into(),
+            paired: 0,
+        };
+        app.request_forget_identities();
+        assert_eq!(status(&app), Some("Inga oanvända identiteter"));
+
+        app.identities = vec![identity("frostlux-a"), identity("frostlux-b"), identity("frostlux-c")];
+        app.request_forget_identities();
+        assert!(matches!(
+            &app.confirm,
+            Some(Confirm::ForgetIdentities { names }) if *names == ["frostlux-a", "frostlux-c"]
+        ));
+        assert!(app.confirm_text().unwrap().starts_with("Glöm 2 oanvända identiteter?"));
+
+        app.answer_confirm(false);
+        assert!(app.confirm.is_none());
+        assert_eq!(app.identities.len(), 3);
+    }
+
+    #[test]
+    fn fades_drop_touched_lights_and_can_be_cancelled() {
+        let mut config = Config::default();
+        config.scenes.fade_minutes = 10.0;
+        let (mut app, gateway) = demo_app(config);
+
+        app.start_fade(Scene::Night).unwrap();
+        let fade = Arc::clone(app.fade.as_ref().unwrap());
+        assert_eq!(fade.active_lights(), 6);
+
+        // Toggled in FrostLux.
+        app.selected = app.lights.iter().position(|l| l.id == 65537).unwrap();
+        app.toggle_selected().unwrap();
+        assert_eq!(fade.active_lights(), 5);
+
+        // Switched off elsewhere, seen through observe.
+        put(&gateway, "15001/65541", json!({ "3311": [{ "5850": 0 }] }));
+        eventually("fade to drop Köksbänk", || {
+            app.poll_refresh();
+            fade.active_lights() == 4
+        });
+        assert_eq!(status(&app), Some("Fade avbruten: Köksbänk"));
+
+        app.cancel_fade();
+        assert!(app.fade.is_none());
+        assert!(!fade.is_running());
+        assert_eq!(status(&app), Some("Fade avbruten: Night"));
+        app.cancel_fade();
+        assert_eq!(status(&app), Some("Ingen fade pågår"));
+    }
+}
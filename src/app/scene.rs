@@ -1,7 +1,37 @@
-use crate::tradfri::{COLOR_TEMP_COLD, COLOR_TEMP_NEUTRAL};
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::time::Duration;
+
+use crate::coap::SceneState;
+use crate::color;
+use crate::tradfri::{Group, Light, Mood, KELVIN_COLD, KELVIN_NEUTRAL};
 
 // ── Scenes ──────────────────────────────────────────────
 
+/// Target state a scene puts each light in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SceneSettings {
+    pub on: bool,
+    /// 0-254
+    pub brightness: u8,
+    /// Colour temperature, e.g. 2700
+    pub kelvin: u16,
+    /// Fade time; `None` uses the gateway's default.
+    pub transition: Option<Duration>,
+}
+
+impl SceneSettings {
+    /// The state written to the gateway; colour temperature only for
+    /// targets that take it (`temperature`).
+    pub fn state(&self, temperature: bool) -> SceneState {
+        SceneState {
+            on: self.on,
+            brightness: self.brightness,
+            mireds: temperature.then(|| color::kelvin_to_mireds(self.kelvin)),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Scene {
     AllOn,
@@ -44,19 +74,21 @@ impl Scene {
         }
     }
 
-    /// Returns (on, brightness 0-254, color_hex).
-    pub fn settings(&self) -> (bool, u8, &str) {
-        match self {
-            Scene::AllOn       => (true,  254, COLOR_TEMP_COLD),
-            Scene::AllOff      => (false, 0,   COLOR_TEMP_COLD),
-            Scene::Movie       => (true,  30,  COLOR_TEMP_NEUTRAL),
-            Scene::Bright      => (true,  254, COLOR_TEMP_COLD),
-            Scene::Cozy        => (true,  127, COLOR_TEMP_NEUTRAL),
-            Scene::Night       => (true,  15,  COLOR_TEMP_NEUTRAL),
-            Scene::Evening     => (true,  150, COLOR_TEMP_NEUTRAL),
-            Scene::Reading     => (true,  200, COLOR_TEMP_COLD),
-            Scene::GoodMorning => (true,  180, COLOR_TEMP_COLD),
-        }
+    pub fn settings(&self) -> SceneSettings {
+        // Fade in tenths of a second; 0 means the gateway's default.
+        let (on, brightness, kelvin, fade) = match self {
+            Scene::AllOn       => (true,  254, KELVIN_COLD,    0),
+            Scene::AllOff      => (false, 0,   KELVIN_COLD,    0),
+            Scene::Movie       => (true,  30,  KELVIN_NEUTRAL, 30),
+            Scene::Bright      => (true,  254, KELVIN_COLD,    0),
+            Scene::Cozy        => (true,  127, KELVIN_NEUTRAL, 0),
+            Scene::Night       => (true,  15,  KELVIN_NEUTRAL, 50),
+            Scene::Evening     => (true,  150, KELVIN_NEUTRAL, 0),
+            Scene::Reading     => (true,  200, KELVIN_COLD,    0),
+            Scene::GoodMorning => (true,  180, KELVIN_COLD,    0),
+        };
+        let transition = (fade > 0).then(|| Duration::from_millis(fade * 100));
+        SceneSettings { on, brightness, kelvin, transition }
     }
 
     /// Parse scene name from string (for CLI).
@@ -83,3 +115,133 @@ impl Scene {
         ]
     }
 }
+
+/// Entry in the scene picker: a built-in scene or a gateway mood.
+#[derive(Debug, Clone)]
+pub enum SceneChoice {
+    Builtin(Scene),
+    Mood(Mood),
+}
+
+// ── Request planning ────────────────────────────────────
+
+/// Requests needed to apply a scene: one per group whose lights are all
+/// targets, plus one per remaining light or plug.
+#[derive(Debug, Default)]
+pub struct ScenePlan {
+    /// Group IDs, each with whether its bulbs take colour temperature
+    pub groups: Vec<(u64, bool)>,
+    /// Bulb IDs, each with whether it takes colour temperature
+    pub lights: Vec<(u64, bool)>,
+    /// Control outlets outside any used group; these only switch on/off.
+    pub plugs: Vec<u64>,
+    /// Blinds and the position (0 = open, 100 = closed) the scene sets.
+    pub blinds: Vec<(u64, u8)>,
+}
+
+/// Cover `targets` with as few requests as possible. `lights` lists every
+/// known light and plug, so remotes and sensors in a group's member list are
+/// ignored. A group request carries one state for all its bulbs, so rooms
+/// mixing bulbs with and without colour temperature go light by light.
+pub fn plan_scene(groups: &[Group], lights: &[Light], targets: &[u64]) -> ScenePlan {
+    let light_ids: Vec<u64> = lights.iter().map(|l| l.id).collect();
+    let temperature = |id: &u64| lights.iter().any(|l| l.id == *id && l.mireds.is_some());
+    let is_plug = |id: &u64| lights.iter().any(|l| l.id == *id && l.plug);
+    let mut remaining: HashSet<u64> = targets.iter().copied().collect();
+    let mut candidates: Vec<(u64, Vec<u64>)> = groups
+        .iter()
+        .map(|g| {
+            let members = g
+                .member_ids
+                .iter()
+                .filter(|id| light_ids.contains(id))
+                .copied()
+                .collect::<Vec<_>>();
+            (g.id, members)
+        })
+        .filter(|(_, members)| !members.is_empty())
+        .collect();
+    // Largest groups first, so a whole-home group beats its rooms.
+    candidates.sort_by_key(|(_, members)| Reverse(members.len()));
+
+    let mut plan = ScenePlan::default();
+    for (group_id, members) in candidates {
+        // A group request hits every member, so only use groups made up
+        // entirely of still-pending targets (no excluded lights).
+        if !members.iter().all(|id| remaining.contains(id)) {
+            continue;
+        }
+        let bulbs: Vec<&u64> = members.iter().filter(|id| !is_plug(id)).collect();
+        let with_temperature = bulbs.iter().filter(|id| temperature(id)).count();
+        if with_temperature != 0 && with_temperature != bulbs.len() {
+            continue;
+        }
+        for id in &members {
+            remaining.remove(id);
+        }
+        plan.groups.push((group_id, with_temperature > 0));
+    }
+    let (plugs, bulbs): (Vec<u64>, Vec<u64>) = targets
+        .iter()
+        .copied()
+        .filter(|id| remaining.contains(id))
+        .partition(is_plug);
+    plan.lights = bulbs.into_iter().map(|id| (id, temperature(&id))).collect();
+    plan.plugs = plugs;
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coap::{self, Backend, MemoryGateway};
+    use crate::tradfri::Snapshot;
+
+    /// Lights and rooms of the demo home, as the app sees them.
+    fn demo_home() -> Snapshot {
+        let (snapshot, _) = coap::connect_and_fetch(&Backend::Memory(MemoryGateway::demo())).unwrap();
+        Snapshot::from(snapshot)
+    }
+
+    fn ids(lights: &[Light]) -> Vec<u64> {
+        lights.iter().map(|l| l.id).collect()
+    }
+
+    #[test]
+    fn rooms_mixing_colour_temperature_go_light_by_light() {
+        let home = demo_home();
+        let plan = plan_scene(&home.groups, &home.lights, &ids(&home.lights));
+        // Kök and Sovrummet have one white spectrum bulb each; Vardagsrum
+        // mixes it with a colour and a plain white bulb.
+        assert_eq!(plan.groups, [(131075, true), (131074, true)]);
+        assert_eq!(plan.lights, [(65538, false), (65539, false), (65537, true)]);
+        assert_eq!(plan.plugs, [65542]);
+        assert!(plan.blinds.is_empty());
+    }
+
+    #[test]
+    fn whole_rooms_go_as_one_request_including_their_plugs() {
+        let mut home = demo_home();
+        for light in home.lights.iter_mut().filter(|l| l.id == 65537) {
+            light.mireds = None;
+        }
+        let plan = plan_scene(&home.groups, &home.lights, &ids(&home.lights));
+        assert_eq!(plan.groups, [(131073, false), (131075, true), (131074, true)]);
+        assert!(plan.lights.is_empty());
+        assert!(plan.plugs.is_empty());
+    }
+
+    #[test]
+    fn rooms_with_excluded_lights_are_not_used() {
+        let home = demo_home();
+        // Sänglampa and the plug left out: Sovrummet and Vardagsrum can't be used.
+        let targets: Vec<u64> = ids(&home.lights)
+            .into_iter()
+            .filter(|&id| id != 65540 && id != 65542)
+            .collect();
+        let plan = plan_scene(&home.groups, &home.lights, &targets);
+        assert_eq!(plan.groups, [(131075, true)]);
+        assert!(plan.lights.iter().all(|(id, _)| *id != 65540));
+        assert!(plan.plugs.is_empty());
+    }
+}
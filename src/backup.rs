@@ -0,0 +1,535 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use crate::coap::{HomeDocuments, SharedTradfriClient};
+use crate::tradfri::unix_now;
+
+/// Written to `version`; files from a newer FrostLux are refused.
+pub const BACKUP_VERSION: u32 = 1;
+/// Light state a mood setting carries; the rest is the gateway's bookkeeping.
+const MOOD_SETTING_KEYS: [&str; 7] = ["9003", "5850", "5851", "5706", "5711", "5709", "5710"];
+
+// ── Backup file ─────────────────────────────────────────
+
+/// Everything the gateway knows, as the JSON it serves, so a backup also
+/// loads into `frostlux sim --home`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Backup {
+    pub version: u32,
+    /// When the backup was taken (Unix seconds)
+    pub created: u64,
+    /// The gateway's `15011/15012` document, without its clock
+    pub gateway: Value,
+    pub devices: Vec<Value>,
+    pub groups: Vec<Value>,
+    /// Moods by group ID
+    pub moods: BTreeMap<u64, Vec<Value>>,
+    /// Kept for reference; they point at device IDs and aren't restored
+    pub smart_tasks: Vec<Value>,
+}
+
+impl Backup {
+    /// Fetch every document from the gateway.
+    pub fn take(client: &SharedTradfriClient) -> Result<Self> {
+        let home = client.home_documents()?;
+        let mut gateway = home.gateway;
+        if let Some(info) = gateway.as_object_mut() {
+            info.remove("9059");
+        }
+        Ok(Self {
+            version: BACKUP_VERSION,
+            created: unix_now(),
+            gateway,
+            devices: home.devices,
+            groups: home.groups,
+            moods: home.moods,
+            smart_tasks: home.smart_tasks,
+        })
+    }
+
+    pub fn load(path: &str) -> Result<Self> {
+        let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+        let backup: Self =
+            serde_json::from_str(&content).with_context(|| format!("Failed to parse {}", path))?;
+        if backup.version > BACKUP_VERSION {
+            anyhow::bail!(
+                "{} is a version {} backup; this FrostLux reads up to version {}",
+                path,
+                backup.version,
+                BACKUP_VERSION
+            );
+        }
+        Ok(backup)
+    }
+
+    pub fn mood_count(&self) -> usize {
+        self.moods.values().map(Vec::len).sum()
+    }
+}
+
+// ── Documents ───────────────────────────────────────────
+
+/// The parts of a device document a restore goes by.
+struct Device {
+    id: u64,
+    name: String,
+    model: String,
+}
+
+impl Device {
+    fn parse(doc: &Value) -> Option<Self> {
+        Some(Self {
+            id: doc["9003"].as_u64()?,
+            name: doc["9001"].as_str()?.to_string(),
+            model: doc["3"]["1"].as_str().unwrap_or_default().to_string(),
+        })
+    }
+}
+
+struct Group {
+    id: u64,
+    name: String,
+    members: Vec<u64>,
+}
+
+impl Group {
+    fn parse(doc: &Value) -> Option<Self> {
+        Some(Self {
+            id: doc["9003"].as_u64()?,
+            name: doc["9001"].as_str()?.to_string(),
+            members: doc["9018"]["15002"]["9003"]
+                .as_array()
+                .map(|ids| ids.iter().filter_map(Value::as_u64).collect())
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/// A mood's per-light settings with device IDs mapped through `device_id`
+/// and only light state kept, sorted by device so equal moods compare equal.
+/// Settings for lights `device_id` doesn't know are left out.
+fn mood_settings(mood: &Value, device_id: impl Fn(u64) -> Option<u64>) -> Vec<Value> {
+    let mut settings: Vec<Value> = mood["15013"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|setting| {
+            let id = device_id(setting["9003"].as_u64()?)?;
+            let mut kept: serde_json::Map<String, Value> = MOOD_SETTING_KEYS
+                .iter()
+                .filter_map(|key| Some((key.to_string(), setting.get(*key)?.clone())))
+                .collect();
+            kept.insert("9003".into(), id.into());
+            Some(Value::Object(kept))
+        })
+        .collect();
+    settings.sort_by_key(|setting| setting["9003"].as_u64());
+    settings
+}
+
+/// `wanted` settings plus the `live` ones for lights `wanted` doesn't cover,
+/// sorted by device: the PUT replaces the whole list, and lights added since
+/// the backup keep what the mood gives them.
+fn merge_settings(wanted: &[Value], live: &[Value]) -> Vec<Value> {
+    let mut merged = wanted.to_vec();
+    merged.extend(
+        live.iter()
+            .filter(|setting| !wanted.iter().any(|w| w["9003"] == setting["9003"]))
+            .cloned(),
+    );
+    merged.sort_by_key(|setting| setting["9003"].as_u64());
+    merged
+}
+
+/// Pair backed-up devices with live ones: same name and model first, then
+/// same ID and model (re-paired in the same order after a reset, but with
+/// factory names), then a model only one device on each side has left.
+/// Returns backup ID → index into `live`.
+fn match_devices(backup: &[Device], live: &[Device]) -> HashMap<u64, usize> {
+    let mut matched = HashMap::new();
+    let mut taken = vec![false; live.len()];
+    let passes: [fn(&Device, &Device) -> bool; 2] = [
+        |b, l| b.model == l.model && b.name.eq_ignore_ascii_case(&l.name),
+        |b, l| b.model == l.model && b.id == l.id,
+    ];
+    for same in passes {
+        for device in backup {
+            if matched.contains_key(&device.id) {
+                continue;
+            }
+            if let Some(i) = (0..live.len()).find(|&i| !taken[i] && same(device, &live[i])) {
+                taken[i] = true;
+                matched.insert(device.id, i);
+            }
+        }
+    }
+    for device in backup {
+        if matched.contains_key(&device.id) || device.model.is_empty() {
+            continue;
+        }
+        let backup_left = backup
+            .iter()
+            .filter(|b| !matched.contains_key(&b.id) && b.model == device.model)
+            .count();
+        let live_left: Vec<usize> = (0..live.len())
+            .filter(|&i| !taken[i] && live[i].model == device.model)
+            .collect();
+        if let (&[i], 1) = (live_left.as_slice(), backup_left) {
+            taken[i] = true;
+            matched.insert(device.id, i);
+        }
+    }
+    matched
+}
+
+// ── Restore ─────────────────────────────────────────────
+
+/// One step of a restore, in the order they are applied.
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    RenameDevice { id: u64, from: String, to: String },
+    RenameGroup { id: u64, from: String, to: String },
+    /// Members as live ID and name
+    CreateGroup { name: String, members: Vec<(u64, String)> },
+    AddMembers { group_id: u64, group: String, members: Vec<(u64, String)> },
+    /// `group_id` is `None` when the group is created by an earlier step.
+    CreateMood { group_id: Option<u64>, group: String, name: String, settings: Vec<Value> },
+    /// `settings` is the whole new list: the backed-up lights' settings with
+    /// the live ones kept for lights the backup doesn't cover.
+    UpdateMood { group_id: u64, mood_id: u64, group: String, name: String, settings: Vec<Value> },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = |members: &[(u64, String)]| {
+            members.iter().map(|(_, name)| name.as_str()).collect::<Vec<_>>().join(", ")
+        };
+        match self {
+            Change::RenameDevice { from, to, .. } => write!(f, "rename device '{}' → '{}'", from, to),
+            Change::RenameGroup { from, to, .. } => write!(f, "rename room '{}' → '{}'", from, to),
+            Change::CreateGroup { name, members } if members.is_empty() => write!(f, "create room '{}'", name),
+            Change::CreateGroup { name, members } => {
+                write!(f, "create room '{}' with {}", name, names(members))
+            }
+            Change::AddMembers { group, members, .. } => write!(f, "add {} to room '{}'", names(members), group),
+            Change::CreateMood { group, name, .. } => write!(f, "create mood '{}' in '{}'", name, group),
+            Change::UpdateMood { group, name, .. } => write!(f, "update mood '{}' in '{}'", name, group),
+        }
+    }
+}
+
+impl Change {
+    pub fn apply(&self, client: &SharedTradfriClient) -> Result<()> {
+        let ids = |members: &[(u64, String)]| members.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        match self {
+            Change::RenameDevice { id, to, .. } => client.rename_device(*id, to),
+            Change::RenameGroup { id, to, .. } => client.rename_group(*id, to),
+            Change::CreateGroup { name, members } => client.create_group(name, &ids(members)),
+            Change::AddMembers { group_id, members, .. } => client.add_group_members(*group_id, &ids(members)),
+            Change::CreateMood { group_id, group, name, settings } => {
+                let group_id = match group_id {
+                    Some(id) => *id,
+                    None => client
+                        .list_groups()?
+                        .into_iter()
+                        .find(|g| g.name == *group)
+                        .map(|g| g.id)
+                        .with_context(|| format!("Room '{}' not found after creating it", group))?,
+                };
+                client.create_mood(group_id, name, settings)
+            }
+            Change::UpdateMood { group_id, mood_id, settings, .. } => {
+                client.set_mood_settings(*group_id, *mood_id, settings)
+            }
+        }
+    }
+}
+
+/// What a restore would do to the live gateway.
+pub struct Plan {
+    pub changes: Vec<Change>,
+    /// Backed-up devices with no counterpart on the gateway ("name (model)")
+    pub missing: Vec<String>,
+}
+
+/// Diff `backup` against the gateway's current documents: names, room
+/// membership and moods that differ become changes. Nothing is removed.
+pub fn plan(backup: &Backup, live: &HomeDocuments) -> Plan {
+    let backup_devices: Vec<Device> = backup.devices.iter().filter_map(Device::parse).collect();
+    let live_devices: Vec<Device> = live.devices.iter().filter_map(Device::parse).collect();
+    let matched = match_devices(&backup_devices, &live_devices);
+
+    let mut changes = Vec::new();
+    let mut missing = Vec::new();
+    // Backup ID → live ID and the name it will have.
+    let mut restored: HashMap<u64, (u64, String)> = HashMap::new();
+    for device in &backup_devices {
+        let Some(&i) = matched.get(&device.id) else {
+            missing.push(format!("{} ({})", device.name, device.model));
+            continue;
+        };
+        let current = &live_devices[i];
+        if current.name != device.name {
+            changes.push(Change::RenameDevice {
+                id: current.id,
+                from: current.name.clone(),
+                to: device.name.clone(),
+            });
+        }
+        restored.insert(device.id, (current.id, device.name.clone()));
+    }
+
+    let backup_groups: Vec<Group> = backup.groups.iter().filter_map(Group::parse).collect();
+    let live_groups: Vec<Group> = live.groups.iter().filter_map(Group::parse).collect();
+    let backup_named = |name: &str| backup_groups.iter().any(|g| g.name.eq_ignore_ascii_case(name));
+    let mut claimed = HashSet::new();
+    let mut mood_changes = Vec::new();
+    for group in &backup_groups {
+        let members: Vec<(u64, String)> = group.members.iter().filter_map(|id| restored.get(id).cloned()).collect();
+        let moods = backup.moods.get(&group.id).map(Vec::as_slice).unwrap_or_default();
+        let settings = |mood: &Value| mood_settings(mood, |id| restored.get(&id).map(|(id, _)| *id));
+        let mood_name = |mood: &Value| mood["9001"].as_str().map(str::to_string);
+
+        // Same name, else same ID unless another backed-up room claims its name.
+        let current = live_groups
+            .iter()
+            .find(|g| !claimed.contains(&g.id) && g.name.eq_ignore_ascii_case(&group.name))
+            .or_else(|| {
+                live_groups
+                    .iter()
+                    .find(|g| !claimed.contains(&g.id) && g.id == group.id && !backup_named(&g.name))
+            });
+        let Some(current) = current else {
+            changes.push(Change::CreateGroup { name: group.name.clone(), members });
+            for mood in moods {
+                let Some(name) = mood_name(mood) else { continue };
+                mood_changes.push(Change::CreateMood {
+                    group_id: None,
+                    group: group.name.clone(),
+                    name,
+                    settings: settings(mood),
+                });
+            }
+            continue;
+        };
+        claimed.insert(current.id);
+
+        if current.name != group.name {
+            changes.push(Change::RenameGroup {
+                id: current.id,
+                from: current.name.clone(),
+                to: group.name.clone(),
+            });
+        }
+        let added: Vec<(u64, String)> =
+            members.into_iter().filter(|(id, _)| !current.members.contains(id)).collect();
+        if !added.is_empty() {
+            changes.push(Change::AddMembers { group_id: current.id, group: group.name.clone(), members: added });
+        }
+
+        let live_moods = live.moods.get(&current.id).map(Vec::as_slice).unwrap_or_default();
+        for mood in moods {
+            let Some(name) = mood_name(mood) else { continue };
+            let wanted = settings(mood);
+            let existing = live_moods
+                .iter()
+                .find(|m| m["9001"].as_str().is_some_and(|n| n.eq_ignore_ascii_case(&name)));
+            match existing.and_then(|m| Some((m["9003"].as_u64()?, mood_settings(m, Some)))) {
+                Some((mood_id, live_settings)) => {
+                    let merged = merge_settings(&wanted, &live_settings);
+                    if merged != live_settings {
+                        mood_changes.push(Change::UpdateMood {
+                            group_id: current.id,
+                            mood_id,
+                            group: group.name.clone(),
+                            name,
+                            settings: merged,
+                        });
+                    }
+                }
+                None => mood_changes.push(Change::CreateMood {
+                    group_id: Some(current.id),
+                    group: group.name.clone(),
+                    name,
+                    settings: wanted,
+                }),
+            }
+        }
+    }
+    // Moods last: their lights must be in the room first.
+    changes.extend(mood_changes);
+    Plan { changes, missing }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const BULB: &str = "TRADFRI bulb E27 WS opal 1000lm";
+
+    fn device(id: u64, name: &str, model: &str) -> Value {
+        json!({ "9003": id, "9001": name, "3": { "0": "IKEA of Sweden", "1": model } })
+    }
+
+    fn room(id: u64, name: &str, members: &[u64]) -> Value {
+        json!({ "9003": id, "9001": name, "9018": { "15002": { "9003": members } } })
+    }
+
+    fn mood(id: u64, name: &str, settings: Value) -> Value {
+        json!({ "9003": id, "9001": name, "15013": settings })
+    }
+
+    fn backup(devices: Vec<Value>, groups: Vec<Value>, moods: BTreeMap<u64, Vec<Value>>) -> Backup {
+        Backup {
+            version: BACKUP_VERSION,
+            created: 0,
+            gateway: json!({}),
+            devices,
+            groups,
+            moods,
+            smart_tasks: Vec::new(),
+        }
+    }
+
+    fn live(devices: Vec<Value>, groups: Vec<Value>, moods: BTreeMap<u64, Vec<Value>>) -> HomeDocuments {
+        HomeDocuments { gateway: json!({}), devices, groups, moods, smart_tasks: Vec::new() }
+    }
+
+    #[test]
+    fn re_paired_devices_are_found_by_name() {
+        let backup = backup(
+            vec![device(65537, "Taklampa", BULB)],
+            vec![room(131073, "Vardagsrum", &[65537])],
+            BTreeMap::new(),
+        );
+        // Re-paired under a new ID; a factory-named bulb now has the old one.
+        let live = live(
+            vec![device(65537, "TRADFRI bulb", BULB), device(65550, "Taklampa", BULB)],
+            vec![room(131073, "Vardagsrum", &[])],
+            BTreeMap::new(),
+        );
+        let plan = plan(&backup, &live);
+        assert_eq!(
+            plan.changes,
+            [Change::AddMembers {
+                group_id: 131073,
+                group: "Vardagsrum".into(),
+                members: vec![(65550, "Taklampa".into())],
+            }]
+        );
+        assert!(plan.missing.is_empty());
+    }
+
+    #[test]
+    fn duplicate_names_match_one_device_each() {
+        let backup = backup(
+            vec![device(65537, "Lampa", BULB), device(65538, "Lampa", BULB)],
+            vec![room(131073, "Vardagsrum", &[65537, 65538])],
+            BTreeMap::new(),
+        );
+        // The first takes the named bulb, the second falls back to its ID.
+        let live = live(
+            vec![device(65600, "Lampa", BULB), device(65538, "TRADFRI bulb", BULB)],
+            vec![room(131073, "Vardagsrum", &[65600])],
+            BTreeMap::new(),
+        );
+        let plan = plan(&backup, &live);
+        assert_eq!(
+            plan.changes,
+            [
+                Change::RenameDevice { id: 65538, from: "TRADFRI bulb".into(), to: "Lampa".into() },
+                Change::AddMembers {
+                    group_id: 131073,
+                    group: "Vardagsrum".into(),
+                    members: vec![(65538, "Lampa".into())],
+                },
+            ]
+        );
+        assert!(plan.missing.is_empty());
+    }
+
+    #[test]
+    fn missing_devices_are_listed_and_left_out() {
+        let backup = backup(
+            vec![device(65537, "Taklampa", BULB), device(65542, "Julgran", "TRADFRI control outlet")],
+            vec![room(131073, "Vardagsrum", &[65537, 65542]), room(131074, "Hall", &[65542])],
+            BTreeMap::from([(
+                131073,
+                vec![mood(196609, "Jul", json!([{ "9003": 65537, "5851": 80 }, { "9003": 65542, "5850": 1 }]))],
+            )]),
+        );
+        let live = live(
+            vec![device(65537, "Taklampa", BULB)],
+            vec![room(131073, "Vardagsrum", &[65537])],
+            BTreeMap::new(),
+        );
+        let plan = plan(&backup, &live);
+        assert_eq!(plan.missing, ["Julgran (TRADFRI control outlet)"]);
+        assert_eq!(
+            plan.changes,
+            [
+                Change::CreateGroup { name: "Hall".into(), members: Vec::new() },
+                Change::CreateMood {
+                    group_id: Some(131073),
+                    group: "Vardagsrum".into(),
+                    name: "Jul".into(),
+                    settings: vec![json!({ "9003": 65537, "5851": 80 })],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn mood_updates_keep_live_settings_the_backup_lacks() {
+        let backup = backup(
+            vec![device(65537, "Taklampa", BULB)],
+            vec![room(131073, "Vardagsrum", &[65537])],
+            BTreeMap::from([(131073, vec![mood(196609, "Avkoppling", json!([{ "9003": 65537, "5851": 100 }]))])]),
+        );
+        // Golvlampa joined the room, and the mood, after the backup.
+        let live_mood = mood(
+            196620,
+            "avkoppling",
+            json!([
+                { "9003": 65539, "5850": 1, "5851": 30, "9002": 1600000000 },
+                { "9003": 65537, "5850": 1, "5851": 254 }
+            ]),
+        );
+        let live = live(
+            vec![device(65537, "Taklampa", BULB), device(65539, "Golvlampa", BULB)],
+            vec![room(131073, "Vardagsrum", &[65537, 65539])],
+            BTreeMap::from([(131073, vec![live_mood])]),
+        );
+        let plan = plan(&backup, &live);
+        assert_eq!(
+            plan.changes,
+            [Change::UpdateMood {
+                group_id: 131073,
+                mood_id: 196620,
+                group: "Vardagsrum".into(),
+                name: "Avkoppling".into(),
+                settings: vec![json!({ "9003": 65537, "5851": 100 }), json!({ "9003": 65539, "5850": 1, "5851": 30 })],
+            }]
+        );
+    }
+
+    #[test]
+    fn moods_already_as_backed_up_are_left_alone() {
+        let settings = json!([{ "9003": 65537, "5850": 1, "5851": 100 }]);
+        let backup = backup(
+            vec![device(65537, "Taklampa", BULB)],
+            vec![room(131073, "Vardagsrum", &[65537])],
+            BTreeMap::from([(131073, vec![mood(196609, "Avkoppling", settings.clone())])]),
+        );
+        let live = live(
+            vec![device(65537, "Taklampa", BULB)],
+            vec![room(131073, "Vardagsrum", &[65537])],
+            BTreeMap::from([(131073, vec![mood(196609, "Avkoppling", settings)])]),
+        );
+        assert!(plan(&backup, &live).changes.is_empty());
+    }
+}
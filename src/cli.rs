@@ -0,0 +1,338 @@
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::app::{
+    forget_identities, load_config, load_identities, record_identity, rename_in_scenes_config, save_gateway_credentials,
+    Config, PairedIdentity,
+};
+use crate::backup::{self, Backup};
+use crate::coap::{self, SharedTradfriClient};
+use crate::tradfri::{self, format_duration, unix_now};
+
+/// Value following `flag` (`--flag value` or `--flag=value`).
+pub fn flag_value(args: &[String], flag: &str) -> Option<String> {
+    let prefix = format!("{}=", flag);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == flag {
+            return iter.next().cloned();
+        }
+        if let Some(value) = arg.strip_prefix(&prefix) {
+            return Some(value.to_string());
+        }
+    }
+    None
+}
+
+/// `frostlux pair --code SECURITY_CODE [--host HOST] [--identity NAME]`
+pub fn run_pair(args: &[String]) -> Result<()> {
+    let code = flag_value(args, "--code")
+        .context("Missing --code (the security code on the back of the gateway)")?;
+    let host = match flag_value(args, "--host") {
+        Some(host) => host,
+        None => load_config().context("Failed to load config")?.gateway.host,
+    };
+    // The gateway refuses to register the same identity twice, so default to
+    // a unique name per pairing.
+    let identity = flag_value(args, "--identity").unwrap_or_else(|| {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        format!("frostlux-{:x}", secs)
+    });
+
+    println!("Pairing with gateway at {} as '{}'...", host, identity);
+    let psk = coap::pair(&host, &code, &identity)?;
+    let path = save_gateway_credentials(&host, &identity, &psk)?;
+    record_identity(PairedIdentity { name: identity, host, psk, paired: unix_now() })?;
+    println!("FrostLux: paired, credentials saved to {}", path.display());
+    Ok(())
+}
+
+/// Ask a yes/no question on the terminal; anything but yes is no.
+fn confirm(question: &str) -> Result<bool> {
+    print!("{} [y/N] ", question);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes" | "j" | "ja"))
+}
+
+/// Connect with the configured credentials (headless commands).
+fn connect(config: &Config) -> Result<SharedTradfriClient> {
+    if config.gateway.identity.is_empty() || config.gateway.psk.is_empty() {
+        anyhow::bail!("Gateway credentials not configured in ~/.config/frostlux/config.toml");
+    }
+    SharedTradfriClient::new(&config.gateway.backend())
+}
+
+/// `frostlux blind` lists blinds; `frostlux blind NAME open|close|stop|POSITION`
+/// moves one (POSITION: 0 = open, 100 = closed).
+pub fn run_blind(args: &[String]) -> Result<()> {
+    let config = load_config().context("Failed to load config")?;
+    let client = connect(&config)?;
+    let blinds = client.list_blinds()?;
+
+    let (name, action) = match args {
+        [] => {
+            if blinds.is_empty() {
+                println!("No blinds paired with the gateway");
+            }
+            for blind in &blinds {
+                let battery = blind.battery.map(|b| format!("  battery {}%", b)).unwrap_or_default();
+                println!("{:<25} {:>3}% open{}", blind.name, 100 - blind.position, battery);
+            }
+            return Ok(());
+        }
+        [name, action] => (name, action),
+        _ => anyhow::bail!("Usage: frostlux blind [NAME open|close|stop|POSITION]"),
+    };
+
+    let blind = blinds
+        .iter()
+        .find(|b| b.name.eq_ignore_ascii_case(name))
+        .with_context(|| format!("Unknown blind: '{}'", name))?;
+    match action.to_lowercase().as_str() {
+        "stop" => {
+            client.stop_blind(blind.id)?;
+            println!("FrostLux: {} stopped", blind.name);
+            return Ok(());
+        }
+        "open" | "up" => client.set_blind_position(blind.id, 0)?,
+        "close" | "down" => client.set_blind_position(blind.id, 100)?,
+        position => {
+            let position: u8 = position
+                .trim_end_matches('%')
+                .parse()
+                .ok()
+                .filter(|p| *p <= 100)
+                .with_context(|| format!("Invalid position '{}': use 0-100, open, close or stop", action))?;
+            client.set_blind_position(blind.id, position)?;
+        }
+    }
+    println!("FrostLux: moving {}", blind.name);
+    Ok(())
+}
+
+/// `frostlux gateway` prints the gateway's firmware, clock, NTP server and
+/// commissioning state, with a warning for each problem found.
+/// `frostlux gateway reboot [--yes]` restarts it after confirmation.
+/// `frostlux gateway identities` lists the identities paired from here and
+/// whether the gateway still accepts them; `frostlux gateway forget NAME…
+/// [--yes]` drops them from that list.
+pub fn run_gateway(args: &[String]) -> Result<()> {
+    let yes = args.iter().any(|a| a == "--yes" || a == "-y");
+    let command: Vec<&String> = args.iter().filter(|a| !a.starts_with('-')).collect();
+    let config = load_config().context("Failed to load config")?;
+    match command[..] {
+        [] => {}
+        [command] if command == "reboot" => {
+            if !yes && !confirm("Reboot the gateway? Lights stay as they are; control returns in about a minute.")? {
+                return Ok(());
+            }
+            connect(&config)?.reboot_gateway()?;
+            println!("FrostLux: gateway rebooting");
+            return Ok(());
+        }
+        [command] if command == "identities" => return list_identities(&config),
+        [command, ref names @ ..] if command == "forget" && !names.is_empty() => {
+            let names: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+            return forget(&config, &names, yes);
+        }
+        _ => anyhow::bail!("Usage: frostlux gateway [reboot [--yes] | identities | forget NAME... [--yes]]"),
+    }
+
+    let client = connect(&config)?;
+    let gateway = tradfri::fetch_gateway(&client)?;
+
+    let or_dash = |text: &str| if text.is_empty() { "–".to_string() } else { text.to_string() };
+    println!("Gateway       {}", or_dash(&gateway.id));
+    println!("Firmware      {}", or_dash(&gateway.firmware));
+    println!("Clock         {}", gateway.clock_label());
+    println!("NTP server    {}", or_dash(&gateway.ntp_server));
+    println!("Commissioning {}", if gateway.commissioning { "open" } else { "off" });
+    if let Some(first_setup) = gateway.first_setup {
+        println!("Set up        {} ago", format_duration(unix_now().saturating_sub(first_setup)));
+    }
+    for warning in gateway.warnings() {
+        println!("Warning: {}", warning);
+    }
+    Ok(())
+}
+
+/// Print each recorded identity with its age, and check it against its
+/// gateway. The configured identity is listed even if it was paired elsewhere.
+fn list_identities(config: &Config) -> Result<()> {
+    let mut identities = load_identities()?;
+    let current = &config.gateway.identity;
+    if !current.is_empty() && !identities.iter().any(|i| i.name == *current) {
+        identities.push(PairedIdentity {
+            name: current.clone(),
+            host: config.gateway.host.clone(),
+            psk: config.gateway.psk.clone(),
+            paired: 0,
+        });
+    }
+    if identities.is_empty() {
+        println!("No identities paired from this machine");
+        return Ok(());
+    }
+    for identity in &identities {
+        let age = match identity.paired {
+            0 => "paired elsewhere".to_string(),
+            paired => format!("paired {} ago", format_duration(unix_now().saturating_sub(paired))),
+        };
+        let status = match coap::check_identity(&identity.host, &identity.name, &identity.psk) {
+            Ok(()) => "accepted".to_string(),
+            Err(e) => format!("not accepted: {}", e),
+        };
+        let marker = if identity.name == *current { " (in use)" } else { "" };
+        println!("{}{}  {}  {}  {}", identity.name, marker, identity.host, age, status);
+    }
+    Ok(())
+}
+
+/// Drop identities from the record. The configured one stays: it is what
+/// FrostLux connects with.
+fn forget(config: &Config, names: &[String], yes: bool) -> Result<()> {
+    if names.contains(&config.gateway.identity) {
+        anyhow::bail!("'{}' is the identity in use; pair again before forgetting it", config.gateway.identity);
+    }
+    let known = load_identities()?;
+    if let Some(name) = names.iter().find(|n| !known.iter().any(|i| i.name == **n)) {
+        anyhow::bail!("No paired identity '{}' (see frostlux gateway identities)", name);
+    }
+    let question = format!(
+        "Forget {}? The gateway still accepts forgotten identities until a factory reset.",
+        names.join(", ")
+    );
+    if !yes && !confirm(&question)? {
+        return Ok(());
+    }
+    forget_identities(names)?;
+    println!("FrostLux: forgot {}", names.join(", "));
+    Ok(())
+}
+
+/// `frostlux rename [--device | --room] OLD NEW` renames a device or a room
+/// on the gateway, then offers to update scene exclusions naming it.
+pub fn run_rename(args: &[String]) -> Result<()> {
+    let only_device = args.iter().any(|a| a == "--device");
+    let only_room = args.iter().any(|a| a == "--room");
+    let names: Vec<&String> = args.iter().filter(|a| !a.starts_with("--")).collect();
+    let [old, new] = names[..] else {
+        anyhow::bail!("Usage: frostlux rename [--device | --room] OLD_NAME NEW_NAME");
+    };
+    let new = new.trim();
+    if new.is_empty() {
+        anyhow::bail!("The new name is empty");
+    }
+
+    let config = load_config().context("Failed to load config")?;
+    let client = connect(&config)?;
+    let snapshot = client.snapshot()?;
+    let device = snapshot.devices.iter().find(|d| d.name.eq_ignore_ascii_case(old));
+    let group = snapshot.groups.iter().find(|g| g.name.eq_ignore_ascii_case(old));
+
+    let old = match (device, group) {
+        (Some(_), Some(_)) if !only_device && !only_room => {
+            anyhow::bail!("'{}' is both a device and a room: add --device or --room", old)
+        }
+        (Some(device), _) if !only_room => {
+            client.rename_device(device.id, new)?;
+            println!("FrostLux: device '{}' renamed to '{}'", device.name, new);
+            device.name.clone()
+        }
+        (_, Some(group)) if !only_device => {
+            client.rename_group(group.id, new)?;
+            println!("FrostLux: room '{}' renamed to '{}'", group.name, new);
+            group.name.clone()
+        }
+        _ if only_room => anyhow::bail!("Unknown room: '{}'", old),
+        _ if only_device => anyhow::bail!("Unknown device: '{}'", old),
+        _ => anyhow::bail!("No device or room named '{}'", old),
+    };
+
+    let references = config.scenes.name_references(&old);
+    let plural = |n: usize| if n == 1 { "entry" } else { "entries" };
+    if references > 0 && confirm(&format!("Update {} scene config {} naming '{}'?", references, plural(references), old))? {
+        let (changed, skipped) = rename_in_scenes_config(&old, new)?;
+        println!("FrostLux: updated {} config {}", changed, plural(changed));
+        if skipped > 0 {
+            println!("FrostLux: {} {} still name '{}'; edit them by hand", skipped, plural(skipped), old);
+        }
+    }
+    Ok(())
+}
+
+/// `frostlux backup > FILE` writes every device, room, mood and smart task
+/// as the gateway serves them to stdout, in a versioned JSON document.
+pub fn run_backup(args: &[String]) -> Result<()> {
+    if !args.is_empty() {
+        anyhow::bail!("Usage: frostlux backup > FILE");
+    }
+    let config = load_config().context("Failed to load config")?;
+    let client = connect(&config)?;
+    let backup = Backup::take(&client)?;
+    println!("{}", serde_json::to_string_pretty(&backup)?);
+    eprintln!(
+        "FrostLux: backed up {} devices, {} rooms, {} moods and {} smart tasks",
+        backup.devices.len(),
+        backup.groups.len(),
+        backup.mood_count(),
+        backup.smart_tasks.len()
+    );
+    Ok(())
+}
+
+/// `frostlux restore FILE [--dry-run] [--yes]` re-applies device and room
+/// names, room members and moods from a backup, after showing what changes.
+pub fn run_restore(args: &[String]) -> Result<()> {
+    let dry_run = args.iter().any(|a| a == "--dry-run");
+    let yes = args.iter().any(|a| a == "--yes" || a == "-y");
+    let files: Vec<&String> = args.iter().filter(|a| !a.starts_with('-')).collect();
+    let [path] = files[..] else {
+        anyhow::bail!("Usage: frostlux restore FILE [--dry-run] [--yes]");
+    };
+
+    let backup = Backup::load(path)?;
+    let config = load_config().context("Failed to load config")?;
+    let client = connect(&config)?;
+    let plan = backup::plan(&backup, &client.home_documents()?);
+
+    for device in &plan.missing {
+        println!("Not on the gateway: {}", device);
+    }
+    match backup.smart_tasks.len() {
+        0 => {}
+        1 => println!("Not restored: 1 smart task (set it up again in the IKEA app)"),
+        n => println!("Not restored: {} smart tasks (set them up again in the IKEA app)", n),
+    }
+    if plan.changes.is_empty() {
+        println!("FrostLux: the gateway already matches the backup");
+        return Ok(());
+    }
+    for change in &plan.changes {
+        println!("  {}", change);
+    }
+    let count = plan.changes.len();
+    let plural = if count == 1 { "change" } else { "changes" };
+    if dry_run || (!yes && !confirm(&format!("Apply {} {}?", count, plural))?) {
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for change in &plan.changes {
+        if let Err(e) = change.apply(&client) {
+            eprintln!("Failed to {}: {:#}", change, e);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!("{} of {} {} failed", failed, count, plural);
+    }
+    println!("FrostLux: {} {} applied", count, plural);
+    Ok(())
+}
@@ -0,0 +1,810 @@
+use anyhow::{Context, Result};
+use coap_lite::block_handler::BlockValue;
+use coap_lite::{CoapOption, CoapRequest, MessageClass, MessageType, Packet, RequestType};
+use openssl::ssl::{Ssl, SslContext, SslMethod, SslOptions, SslVerifyMode};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use super::{Transport, OBSERVE_RESUBSCRIBE};
+use crate::discovery;
+
+/// Largest datagram we read; bigger payloads arrive block-wise (Block2).
+const BUF_SIZE: usize = 4096;
+/// GET requests kept in flight at once on one session while fetching lists.
+/// The gateway answers them in any order; more than this overruns its queue.
+const PIPELINE_WINDOW: usize = 8;
+/// DTLS handshake timeout. Requests use `RetryPolicy`.
+const TIMEOUT_SECS: u64 = 3;
+/// How often an idle observe session checks whether it should stop.
+const STOP_POLL: Duration = Duration::from_millis(500);
+/// RFC 7252 ACK_RANDOM_FACTOR: the first timeout is randomised up to this
+/// multiple of `ack_timeout`, so clients don't retransmit in lockstep.
+const ACK_RANDOM_FACTOR: f32 = 1.5;
+/// Ceiling for any one wait, however large the configured `ack_timeout` and
+/// `max_retransmit`; also keeps the doubling from overflowing `Duration`.
+const MAX_WAIT: Duration = Duration::from_secs(300);
+
+// ── DTLS transport ──────────────────────────────────────
+
+/// UDP channel that implements Read/Write for openssl
+#[derive(Debug)]
+struct UdpChannel {
+    socket: UdpSocket,
+    remote_addr: SocketAddr,
+}
+
+impl Read for UdpChannel {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.socket.recv(buf)
+    }
+}
+
+impl Write for UdpChannel {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.socket.send_to(buf, self.remote_addr)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Confirmable-message retransmission (RFC 7252 §4.2) and reconnect limits.
+///
+/// A lost request is first retransmitted on the same DTLS session, waiting
+/// twice as long each time; only when that fails is the session torn down
+/// and re-handshaken, which costs seconds.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Wait before the first retransmission
+    pub ack_timeout: Duration,
+    /// Retransmissions on one session before reconnecting
+    pub max_retransmit: u32,
+    /// Full DTLS reconnects per request before giving up
+    pub reconnects: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            ack_timeout: Duration::from_secs(1),
+            max_retransmit: 2,
+            reconnects: 1,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// First timeout, randomised between `ack_timeout` and
+    /// `ack_timeout * ACK_RANDOM_FACTOR`.
+    fn initial_timeout(&self) -> Duration {
+        let jitter = (random_seed() % 1000) as f32 / 1000.0;
+        self.ack_timeout
+            .min(MAX_WAIT)
+            .mul_f32(1.0 + (ACK_RANDOM_FACTOR - 1.0) * jitter)
+    }
+
+    /// How long to wait for a separate response once the request is
+    /// acknowledged: as long as all retransmissions would have taken.
+    fn response_timeout(&self) -> Duration {
+        self.ack_timeout
+            .checked_mul(2u32.saturating_pow(self.max_retransmit.min(16)))
+            .map_or(MAX_WAIT, |timeout| timeout.min(MAX_WAIT))
+    }
+}
+
+/// The next retransmission timeout (RFC 7252 exponential backoff).
+fn doubled(timeout: Duration) -> Duration {
+    timeout.saturating_mul(2).min(MAX_WAIT)
+}
+
+/// Random value for message IDs and tokens, falling back to the clock:
+/// uniqueness matters here, not secrecy.
+fn random_seed() -> u32 {
+    let mut bytes = [0u8; 4];
+    if openssl::rand::rand_bytes(&mut bytes).is_ok() {
+        return u32::from_be_bytes(bytes);
+    }
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos())
+}
+
+/// How a received packet relates to an outstanding request.
+#[derive(Debug, PartialEq)]
+enum Reply {
+    /// Empty ACK: the answer follows in a separate message
+    Acknowledged,
+    /// The answer, piggybacked on the ACK or sent separately
+    Response,
+    /// The gateway rejected the request
+    Reset,
+    /// Not for this request
+    Unrelated,
+}
+
+/// Match `packet` to `request`: ACKs and resets by message ID, responses by
+/// token as well.
+fn classify_reply(request: &Packet, packet: &Packet) -> Reply {
+    let same_id = packet.header.message_id == request.header.message_id;
+    let same_token = packet.get_token() == request.get_token();
+    let is_response = matches!(packet.header.code, MessageClass::Response(_));
+    match packet.header.get_type() {
+        MessageType::Acknowledgement if same_id && packet.header.code == MessageClass::Empty => {
+            Reply::Acknowledged
+        }
+        MessageType::Acknowledgement if same_id && same_token => Reply::Response,
+        MessageType::Reset if same_id => Reply::Reset,
+        MessageType::Confirmable | MessageType::NonConfirmable if same_token && is_response => {
+            Reply::Response
+        }
+        _ => Reply::Unrelated,
+    }
+}
+
+/// A pipelined GET waiting for its response.
+struct InFlight {
+    /// Position in the caller's path list
+    index: usize,
+    request: Packet,
+    timeout: Duration,
+    deadline: Instant,
+    retransmits: u32,
+    acknowledged: bool,
+}
+
+/// Outcome of a pipelined GET, or `None` if it continues block-wise and has
+/// to be fetched through `get`.
+fn pipelined_result(path: &str, response: Packet) -> Option<Result<Vec<u8>>> {
+    if let Some(Ok(block)) = response.get_first_option_as::<BlockValue>(CoapOption::Block2) {
+        if block.more || block.num > 0 {
+            return None;
+        }
+    }
+    if let MessageClass::Response(code) = response.header.code {
+        use coap_lite::ResponseType::*;
+        if !matches!(code, Content | Created | Changed | Deleted | Valid) {
+            return Some(Err(anyhow::anyhow!(
+                "CoAP error {:?} for {}: {}",
+                code,
+                path,
+                String::from_utf8_lossy(&response.payload)
+            )));
+        }
+    }
+    Some(Ok(response.payload))
+}
+
+/// Persistent DTLS stream over UDP for CoAP communication.
+pub struct DtlsCoap {
+    host: String,
+    identity: String,
+    psk: String,
+    stream: Option<openssl::ssl::SslStream<UdpChannel>>,
+    msg_id: u16,
+    retry: RetryPolicy,
+}
+
+impl DtlsCoap {
+    pub fn new(host: &str, identity: &str, psk: &str, retry: RetryPolicy) -> Result<Self> {
+        let mut this = Self {
+            host: host.to_string(),
+            identity: identity.to_string(),
+            psk: psk.to_string(),
+            stream: None,
+            // Start somewhere random so a restarted client doesn't reuse IDs
+            // the gateway still remembers for deduplication.
+            msg_id: random_seed() as u16,
+            retry,
+        };
+        this.ensure_connected()?;
+        Ok(this)
+    }
+
+    fn ensure_connected(&mut self) -> Result<()> {
+        if self.stream.is_none() {
+            self.stream = Some(Self::connect_stream(&self.host, &self.identity, &self.psk)?);
+        }
+        Ok(())
+    }
+
+    /// Connect to Trådfri gateway via DTLS/PSK. The host is resolved afresh
+    /// on every connect; an auto-discovered address that stops answering is
+    /// forgotten, so the next attempt browses again.
+    fn connect_stream(
+        host: &str,
+        identity: &str,
+        psk: &str,
+    ) -> Result<openssl::ssl::SslStream<UdpChannel>> {
+        let addr = discovery::resolve_host(host)?;
+        let stream = Self::connect_resolved(addr, identity, psk);
+        if stream.is_err() && discovery::is_auto(host) {
+            discovery::forget_cached();
+        }
+        stream
+    }
+
+    fn connect_resolved(
+        addr: SocketAddr,
+        identity: &str,
+        psk: &str,
+    ) -> Result<openssl::ssl::SslStream<UdpChannel>> {
+        let local = if addr.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" };
+        let socket = UdpSocket::bind(local).context("Failed to bind UDP socket")?;
+        socket.set_read_timeout(Some(Duration::from_secs(TIMEOUT_SECS)))?;
+        socket.set_write_timeout(Some(Duration::from_secs(TIMEOUT_SECS)))?;
+        socket.connect(addr)?;
+
+        let channel = UdpChannel {
+            socket,
+            remote_addr: addr,
+        };
+
+        let identity_bytes = identity.as_bytes().to_vec();
+        let psk_bytes = psk.as_bytes().to_vec();
+
+        let mut ctx = SslContext::builder(SslMethod::dtls())
+            .context("Failed to create DTLS context")?;
+
+        // OpenSSL 3 kan blockera legacy PSK-ciphers via security level.
+        // Trådfri gateway kräver PSK-AES128-CCM8 över DTLS.
+        ctx.set_cipher_list("PSK-AES128-CCM8:@SECLEVEL=0")
+            .context("Failed to set cipher")?;
+        ctx.set_verify(SslVerifyMode::NONE);
+        ctx.set_options(SslOptions::ALLOW_UNSAFE_LEGACY_RENEGOTIATION);
+
+        ctx.set_psk_client_callback(move |_ssl, _hint, mut identity_buf, mut psk_buf| {
+            identity_buf.write_all(&identity_bytes).ok();
+            psk_buf.write_all(&psk_bytes).ok();
+            Ok(psk_bytes.len())
+        });
+
+        let ssl_ctx = ctx.build();
+        let mut ssl = Ssl::new(&ssl_ctx).context("Failed to create SSL instance")?;
+        ssl.set_connect_state();
+
+        ssl.connect(channel)
+            .map_err(|e| anyhow::anyhow!("DTLS handshake failed: {:?}", e))
+    }
+
+    fn request(&mut self, mut request: Packet) -> Result<Packet> {
+        request.set_token(Self::new_token());
+        let bytes = request
+            .to_bytes()
+            .context("Failed to serialize CoAP request")?;
+
+        // Retransmissions happen inside `exchange`; a fresh DTLS session is
+        // only worth its handshake once the current one stops answering.
+        let mut last_err = None;
+        for attempt in 0..=self.retry.reconnects {
+            if attempt > 0 {
+                tracing::info!("reconnecting to gateway ({}/{})", attempt, self.retry.reconnects);
+            }
+            self.ensure_connected()?;
+
+            match self.exchange(&request, &bytes) {
+                Ok(packet) => return Ok(packet),
+                Err(e) => {
+                    last_err = Some(e);
+                    self.stream = None;
+                }
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| anyhow::anyhow!("DTLS request failed")))
+    }
+
+    /// Send `bytes` on the current session and wait for the response,
+    /// retransmitting with exponential backoff until it's acknowledged.
+    fn exchange(&mut self, request: &Packet, bytes: &[u8]) -> Result<Packet> {
+        let mut timeout = self.retry.initial_timeout();
+        for attempt in 0..=self.retry.max_retransmit {
+            if attempt > 0 {
+                tracing::debug!(
+                    "retransmitting message {} ({}/{})",
+                    request.header.message_id,
+                    attempt,
+                    self.retry.max_retransmit
+                );
+            }
+            let stream = self
+                .stream
+                .as_mut()
+                .context("DTLS stream is not connected")?;
+            stream.write_all(bytes)?;
+
+            if let Some(packet) = self.await_response(request, timeout)? {
+                return Ok(packet);
+            }
+            timeout = doubled(timeout);
+        }
+        anyhow::bail!(
+            "No response from gateway after {} retransmissions",
+            self.retry.max_retransmit
+        )
+    }
+
+    /// Read until the response to `request` arrives, matched by message ID
+    /// (piggybacked ACK) or token (separate response). Anything else is a
+    /// late reply to an earlier request or an unsolicited message and is
+    /// dropped, so it can never be mistaken for this request's answer.
+    ///
+    /// Returns `None` if nothing acknowledged the request within `timeout`.
+    fn await_response(&mut self, request: &Packet, timeout: Duration) -> Result<Option<Packet>> {
+        let mut deadline = Instant::now() + timeout;
+        let mut acknowledged = false;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                if acknowledged {
+                    anyhow::bail!(
+                        "Gateway acknowledged request {} but never answered",
+                        request.header.message_id
+                    );
+                }
+                return Ok(None);
+            }
+            let Some(packet) = self.read_packet(remaining)? else {
+                continue;
+            };
+
+            let reply = classify_reply(request, &packet);
+            self.settle(&packet, reply == Reply::Response)?;
+            match reply {
+                Reply::Acknowledged => {
+                    // The gateway has the request and answers later in a
+                    // separate message. Stop retransmitting.
+                    if !acknowledged {
+                        acknowledged = true;
+                        deadline = Instant::now() + self.retry.response_timeout();
+                    }
+                }
+                Reply::Response => return Ok(Some(packet)),
+                Reply::Reset => {
+                    anyhow::bail!("Gateway reset request {}", request.header.message_id);
+                }
+                Reply::Unrelated => {
+                    tracing::debug!("discarding stray message {}", packet.header.message_id);
+                }
+            }
+        }
+    }
+
+    /// ACK a separate response, or reject an unrelated confirmable message.
+    fn settle(&mut self, packet: &Packet, ours: bool) -> Result<()> {
+        if packet.header.get_type() != MessageType::Confirmable {
+            return Ok(());
+        }
+        let kind = if ours { MessageType::Acknowledgement } else { MessageType::Reset };
+        self.reply_empty(packet.header.message_id, kind)
+    }
+
+    fn pipeline(
+        &mut self,
+        paths: &[String],
+        results: &mut [Option<Result<Vec<u8>>>],
+    ) -> Result<()> {
+        self.ensure_connected()?;
+        let mut next = 0;
+        let mut in_flight: Vec<InFlight> = Vec::new();
+        loop {
+            while in_flight.len() < PIPELINE_WINDOW && next < paths.len() {
+                let mut request: CoapRequest<SocketAddr> = CoapRequest::new();
+                request.set_method(RequestType::Get);
+                request.set_path(&paths[next]);
+                request.message.header.message_id = self.next_msg_id();
+                request.message.set_token(Self::new_token());
+                self.send(&request.message)?;
+                let timeout = self.retry.initial_timeout();
+                in_flight.push(InFlight {
+                    index: next,
+                    request: request.message,
+                    timeout,
+                    deadline: Instant::now() + timeout,
+                    retransmits: 0,
+                    acknowledged: false,
+                });
+                next += 1;
+            }
+            let Some(deadline) = in_flight.iter().map(|r| r.deadline).min() else {
+                return Ok(());
+            };
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if !remaining.is_zero() {
+                if let Some(packet) = self.read_packet(remaining)? {
+                    let found = in_flight.iter().enumerate().find_map(|(i, r)| {
+                        match classify_reply(&r.request, &packet) {
+                            Reply::Unrelated => None,
+                            reply => Some((i, reply)),
+                        }
+                    });
+                    self.settle(&packet, matches!(found, Some((_, Reply::Response))))?;
+                    match found {
+                        Some((i, Reply::Acknowledged)) => {
+                            let r = &mut in_flight[i];
+                            if !r.acknowledged {
+                                r.acknowledged = true;
+                                r.deadline = Instant::now() + self.retry.response_timeout();
+                            }
+                        }
+                        Some((i, Reply::Response)) => {
+                            let r = in_flight.swap_remove(i);
+                            results[r.index] = pipelined_result(&paths[r.index], packet);
+                        }
+                        Some((i, Reply::Reset)) => {
+                            let r = in_flight.swap_remove(i);
+                            results[r.index] = Some(Err(anyhow::anyhow!(
+                                "Gateway reset request for {}",
+                                paths[r.index]
+                            )));
+                        }
+                        _ => tracing::debug!("discarding stray message {}", packet.header.message_id),
+                    }
+                }
+            }
+
+            // Retransmit what timed out; give up on the rest for now.
+            let now = Instant::now();
+            let mut i = 0;
+            while i < in_flight.len() {
+                let r = &mut in_flight[i];
+                if r.deadline > now {
+                    i += 1;
+                    continue;
+                }
+                if r.acknowledged || r.retransmits >= self.retry.max_retransmit {
+                    in_flight.swap_remove(i);
+                    continue;
+                }
+                r.retransmits += 1;
+                r.timeout = doubled(r.timeout);
+                r.deadline = now + r.timeout;
+                self.send(&in_flight[i].request)?;
+                i += 1;
+            }
+        }
+    }
+
+    /// Random 4-byte request token. Observe registrations use the observed
+    /// path's index as an 8-byte token, so the two never collide.
+    fn new_token() -> Vec<u8> {
+        random_seed().to_be_bytes().to_vec()
+    }
+
+    fn next_msg_id(&mut self) -> u16 {
+        let id = self.msg_id;
+        self.msg_id = self.msg_id.wrapping_add(1);
+        id
+    }
+
+    /// Register an RFC 7641 observation of `paths[index]`, retransmitting
+    /// like `exchange` until the gateway answers. Notifications for paths
+    /// registered earlier keep going to `notify` meanwhile. Returns `false`
+    /// once `notify` does.
+    fn register(
+        &mut self,
+        paths: &[String],
+        index: usize,
+        notify: &mut dyn FnMut(&str, &[u8]) -> bool,
+    ) -> Result<bool> {
+        let path = &paths[index];
+        let mut request: CoapRequest<SocketAddr> = CoapRequest::new();
+        request.set_method(RequestType::Get);
+        request.set_path(path);
+        request.message.header.message_id = self.next_msg_id();
+        // 8-byte tokens (the path's index) never collide with the 4-byte
+        // tokens of ordinary requests.
+        request.message.set_token((index as u64).to_be_bytes().to_vec());
+        request.message.set_observe_value(0);
+        let request = request.message;
+
+        let mut timeout = self.retry.initial_timeout();
+        let mut acknowledged = false;
+        for _ in 0..=self.retry.max_retransmit {
+            if !acknowledged {
+                self.send(&request)?;
+            }
+            let mut deadline = Instant::now() + timeout;
+            loop {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    break;
+                }
+                let Some(packet) = self.read_packet(remaining)? else { continue };
+                match classify_reply(&request, &packet) {
+                    Reply::Response => return self.handle_notification(paths, packet, notify),
+                    Reply::Acknowledged if !acknowledged => {
+                        // Answered later in a separate message; stop retransmitting.
+                        acknowledged = true;
+                        deadline = Instant::now() + self.retry.response_timeout();
+                    }
+                    Reply::Acknowledged => {}
+                    Reply::Reset => anyhow::bail!("Gateway reset observe registration for {}", path),
+                    Reply::Unrelated => {
+                        if !self.handle_notification(paths, packet, notify)? {
+                            return Ok(false);
+                        }
+                    }
+                }
+            }
+            if acknowledged {
+                anyhow::bail!("Gateway acknowledged observe registration for {} but never answered", path);
+            }
+            timeout = doubled(timeout);
+        }
+        anyhow::bail!(
+            "No response to observe registration for {} after {} retransmissions",
+            path,
+            self.retry.max_retransmit
+        )
+    }
+
+    /// Send a packet without waiting for a reply.
+    fn send(&mut self, packet: &Packet) -> Result<()> {
+        let bytes = packet.to_bytes().context("Failed to serialize CoAP packet")?;
+        self.ensure_connected()?;
+        let stream = self.stream.as_mut().context("DTLS stream is not connected")?;
+        stream.write_all(&bytes)?;
+        Ok(())
+    }
+
+    /// Read the next packet within `timeout`. `None` if it expires first or
+    /// the datagram isn't valid CoAP.
+    fn read_packet(&mut self, timeout: Duration) -> Result<Option<Packet>> {
+        let Some(datagram) = self.read_datagram(timeout)? else {
+            return Ok(None);
+        };
+        match Packet::from_bytes(&datagram) {
+            Ok(packet) => Ok(Some(packet)),
+            Err(e) => {
+                tracing::debug!("discarding unparsable CoAP packet: {:?}", e);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Read one datagram, waiting at most `timeout`.
+    fn read_datagram(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>> {
+        let stream = self.stream.as_mut().context("DTLS stream is not connected")?;
+        stream.get_ref().socket.set_read_timeout(Some(timeout))?;
+        let mut buf = [0u8; BUF_SIZE];
+        match stream.read(&mut buf) {
+            Ok(len) => Ok(Some(buf[..len].to_vec())),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Send an empty ACK or RST for a message received from the gateway.
+    fn reply_empty(&mut self, message_id: u16, kind: MessageType) -> Result<()> {
+        let mut packet = Packet::new();
+        packet.header.set_type(kind);
+        packet.header.code = MessageClass::Empty;
+        packet.header.message_id = message_id;
+        self.send(&packet)
+    }
+
+    /// Acknowledge a notification and pass its payload to `notify`. Returns
+    /// `false` once `notify` does.
+    fn handle_notification(
+        &mut self,
+        paths: &[String],
+        packet: Packet,
+        notify: &mut dyn FnMut(&str, &[u8]) -> bool,
+    ) -> Result<bool> {
+        let token: Option<[u8; 8]> = packet.get_token().try_into().ok();
+        let path = token
+            .map(u64::from_be_bytes)
+            .and_then(|index| paths.get(index as usize));
+        let Some(path) = path else {
+            // Not one of our registrations — ask the gateway to stop sending it.
+            if packet.header.get_type() == MessageType::Confirmable {
+                self.reply_empty(packet.header.message_id, MessageType::Reset)?;
+            }
+            return Ok(true);
+        };
+        if packet.header.get_type() == MessageType::Confirmable {
+            self.reply_empty(packet.header.message_id, MessageType::Acknowledgement)?;
+        }
+
+        match packet.header.code {
+            MessageClass::Response(coap_lite::ResponseType::Content) => {}
+            MessageClass::Response(code) => {
+                tracing::warn!("observe of {} rejected: {:?}", path, code);
+                return Ok(true);
+            }
+            _ => return Ok(true),
+        }
+        Ok(notify(path, &packet.payload))
+    }
+}
+
+impl Drop for DtlsCoap {
+    /// Say goodbye (DTLS close_notify), so the gateway frees the session
+    /// now instead of holding it until it times out.
+    fn drop(&mut self) {
+        if let Some(stream) = self.stream.as_mut() {
+            let _ = stream.shutdown();
+        }
+    }
+}
+
+impl Transport for DtlsCoap {
+    /// Send a CoAP GET request. Block-wise responses (Block2) are fetched
+    /// block by block and returned as one payload.
+    fn get(&mut self, path: &str) -> Result<Vec<u8>> {
+        let mut payload = Vec::new();
+        let mut next_block: Option<BlockValue> = None;
+        loop {
+            let mut request: CoapRequest<SocketAddr> = CoapRequest::new();
+            request.set_method(RequestType::Get);
+            request.set_path(path);
+            request.message.header.message_id = self.next_msg_id();
+            if let Some(block) = next_block.take() {
+                request.message.add_option_as(CoapOption::Block2, block);
+            }
+
+            let response = self.request(request.message)?;
+
+            if let MessageClass::Response(ref code) = response.header.code {
+                use coap_lite::ResponseType::*;
+                match code {
+                    Content | Created | Changed | Deleted | Valid => {}
+                    _ => {
+                        anyhow::bail!(
+                            "CoAP error {:?}: {}",
+                            code,
+                            String::from_utf8_lossy(&response.payload)
+                        );
+                    }
+                }
+            }
+
+            let block = match response.get_first_option_as::<BlockValue>(CoapOption::Block2) {
+                None => None,
+                Some(Ok(block)) => Some(block),
+                Some(Err(e)) => anyhow::bail!("Invalid Block2 option from {}: {:?}", path, e),
+            };
+            let Some(block) = block else {
+                // Not block-wise (or the gateway fell back to a whole response).
+                if payload.is_empty() {
+                    return Ok(response.payload);
+                }
+                anyhow::bail!("Block-wise transfer of {} ended without Block2", path);
+            };
+
+            // Blocks must arrive in order; the offset is implied by num and size.
+            if block.num as usize * block.size() != payload.len() {
+                anyhow::bail!(
+                    "Block-wise transfer of {} out of order: block {} at offset {}",
+                    path,
+                    block.num,
+                    payload.len()
+                );
+            }
+            payload.extend_from_slice(&response.payload);
+            if !block.more {
+                return Ok(payload);
+            }
+            let num = block
+                .num
+                .checked_add(1)
+                .with_context(|| format!("Block-wise transfer of {} too large", path))?;
+            next_block = Some(BlockValue { num, more: false, size_exponent: block.size_exponent });
+        }
+    }
+
+    /// Send a CoAP PUT request with JSON payload
+    fn put(&mut self, path: &str, payload: &[u8]) -> Result<()> {
+        let mut request: CoapRequest<SocketAddr> = CoapRequest::new();
+        request.set_method(RequestType::Put);
+        request.set_path(path);
+        request.message.header.message_id = self.next_msg_id();
+        request.message.payload = payload.to_vec();
+
+        let response = self.request(request.message)?;
+
+        match response.header.code {
+            MessageClass::Response(ref code) => {
+                use coap_lite::ResponseType::*;
+                match code {
+                    Content | Created | Changed | Deleted | Valid => Ok(()),
+                    _ => {
+                        anyhow::bail!(
+                            "CoAP PUT error {:?}: {}",
+                            code,
+                            String::from_utf8_lossy(&response.payload)
+                        );
+                    }
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Send a CoAP POST request with JSON payload and return the response body
+    fn post(&mut self, path: &str, payload: &[u8]) -> Result<Vec<u8>> {
+        let mut request: CoapRequest<SocketAddr> = CoapRequest::new();
+        request.set_method(RequestType::Post);
+        request.set_path(path);
+        request.message.header.message_id = self.next_msg_id();
+        request.message.payload = payload.to_vec();
+
+        let response = self.request(request.message)?;
+
+        if let MessageClass::Response(ref code) = response.header.code {
+            use coap_lite::ResponseType::*;
+            match code {
+                Content | Created | Changed | Deleted | Valid => {}
+                _ => {
+                    anyhow::bail!(
+                        "CoAP POST error {:?}: {}",
+                        code,
+                        String::from_utf8_lossy(&response.payload)
+                    );
+                }
+            }
+        }
+
+        Ok(response.payload)
+    }
+
+    /// GET several resources, keeping up to `PIPELINE_WINDOW` requests in
+    /// flight on this session and matching responses by token as they
+    /// arrive. Whatever the pipeline can't finish (lost requests, block-wise
+    /// responses, a broken session) is fetched through `get`, one at a time.
+    fn get_many(&mut self, paths: &[String]) -> Vec<Result<Vec<u8>>> {
+        let mut results: Vec<Option<Result<Vec<u8>>>> = paths.iter().map(|_| None).collect();
+        if let Err(e) = self.pipeline(paths, &mut results) {
+            tracing::warn!("pipelined fetch interrupted: {}", e);
+            self.stream = None;
+        }
+        results
+            .into_iter()
+            .zip(paths)
+            .map(|(result, path)| result.unwrap_or_else(|| self.get(path)))
+            .collect()
+    }
+
+    /// Register an RFC 7641 observation for every path, one at a time and
+    /// waiting for each confirmation so a dead session is noticed before we
+    /// settle into the read loop. Re-registers after `OBSERVE_RESUBSCRIBE`
+    /// without traffic, which doubles as a liveness check.
+    fn observe(
+        &mut self,
+        paths: &[String],
+        stop: &AtomicBool,
+        notify: &mut dyn FnMut(&str, &[u8]) -> bool,
+    ) -> Result<()> {
+        loop {
+            for index in 0..paths.len() {
+                if stop.load(Ordering::Relaxed) || !self.register(paths, index, notify)? {
+                    return Ok(());
+                }
+            }
+            tracing::info!("observing {} resources", paths.len());
+
+            let mut last_traffic = Instant::now();
+            while last_traffic.elapsed() < OBSERVE_RESUBSCRIBE {
+                if stop.load(Ordering::Relaxed) {
+                    return Ok(());
+                }
+                let Some(packet) = self.read_packet(STOP_POLL)? else { continue };
+                last_traffic = Instant::now();
+                if !self.handle_notification(paths, packet, notify)? {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,386 @@
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::{Transport, GATEWAY_INFO_PATH, GATEWAY_REBOOT_PATH, SMART_TASKS_PATH};
+
+/// Light state keys a group PUT passes on to its members.
+const GROUP_LIGHT_KEYS: [&str; 4] = ["5850", "5851", "5706", "5711"];
+/// The gateway numbers each kind of resource from its own base.
+const FIRST_GROUP_ID: u64 = 131073;
+const FIRST_MOOD_ID: u64 = 196609;
+/// Top-level collections, listed (empty) even in a home without any.
+const COLLECTIONS: [&str; 3] = ["15001", "15004", SMART_TASKS_PATH];
+/// How often a quiet observation checks whether it should stop.
+const STOP_POLL: Duration = Duration::from_millis(100);
+
+// ── In-memory gateway ───────────────────────────────────
+
+/// A gateway held in memory: every resource is a JSON document keyed by its
+/// path, PUTs merge into it and observers hear about the change, as with the
+/// real gateway. Clones share state, so one clone can serve the client's
+/// session and another the observer's.
+#[derive(Clone, Default)]
+pub struct MemoryGateway {
+    state: Arc<Mutex<State>>,
+}
+
+type Observer = (Vec<String>, mpsc::Sender<(String, Vec<u8>)>);
+
+#[derive(Default)]
+struct State {
+    resources: BTreeMap<String, Value>,
+    observers: Vec<Observer>,
+}
+
+impl State {
+    /// Store `value` at `collection/<9003>` and list its ID in `collection`.
+    fn insert(&mut self, collection: &str, value: Value) -> u64 {
+        let id = value["9003"].as_u64().unwrap_or_default();
+        self.resources.insert(format!("{}/{}", collection, id), value);
+        let list = self
+            .resources
+            .entry(collection.to_string())
+            .or_insert_with(|| json!([]));
+        if let Some(ids) = list.as_array_mut() {
+            ids.push(id.into());
+        }
+        id
+    }
+
+    /// A fresh instance ID: one past the highest in use from `first` on.
+    fn next_id(&self, first: u64) -> u64 {
+        self.resources
+            .values()
+            .filter_map(|value| value["9003"].as_u64())
+            .filter(|id| (first..first + 65536).contains(id))
+            .fold(first, |next, id| next.max(id + 1))
+    }
+
+    /// `15004/add`: append devices to a group's members. Returns the group's path.
+    fn add_members(&mut self, patch: &Value) -> Result<String> {
+        let path = format!("15004/{}", patch["9003"].as_u64().unwrap_or_default());
+        let group = self
+            .resources
+            .get_mut(&path)
+            .with_context(|| format!("CoAP error NotFound: {}", path))?;
+        let members = &mut group["9018"]["15002"]["9003"];
+        if !members.is_array() {
+            *members = json!([]);
+        }
+        if let (Some(members), Some(ids)) = (members.as_array_mut(), patch["9018"]["15002"]["9003"].as_array()) {
+            for id in ids {
+                if !members.contains(id) {
+                    members.push(id.clone());
+                }
+            }
+        }
+        Ok(path)
+    }
+
+    /// Send the current state of `paths` to everyone observing them.
+    fn notify(&mut self, paths: &[String]) {
+        for path in paths {
+            let Some(value) = self.resources.get(path) else { continue };
+            let payload = value.to_string().into_bytes();
+            // Observers whose session has ended are dropped here.
+            self.observers.retain(|(watched, tx)| {
+                !watched.contains(path) || tx.send((path.clone(), payload.clone())).is_ok()
+            });
+        }
+    }
+
+    /// Pass a group PUT on to its members, as the gateway does: plain state
+    /// keys to every light, a mood (`9039`) as each member's stored state.
+    /// Returns the member paths that changed.
+    fn apply_group(&mut self, group_id: &str, patch: &Value) -> Vec<String> {
+        let members: Vec<u64> = self
+            .resources
+            .get(&format!("15004/{}", group_id))
+            .and_then(|g| g["9018"]["15002"]["9003"].as_array())
+            .map(|ids| ids.iter().filter_map(Value::as_u64).collect())
+            .unwrap_or_default();
+        let mood: Vec<Value> = patch
+            .get("9039")
+            .and_then(Value::as_u64)
+            .and_then(|mood| self.resources.get(&format!("15005/{}/{}", group_id, mood)))
+            .and_then(|mood| mood["15013"].as_array().cloned())
+            .unwrap_or_default();
+        let shared: serde_json::Map<String, Value> = GROUP_LIGHT_KEYS
+            .iter()
+            .filter_map(|key| Some((key.to_string(), patch.get(*key)?.clone())))
+            .collect();
+
+        let mut changed = Vec::new();
+        for id in members {
+            let mut light = Value::Object(shared.clone());
+            if let Some(setting) = mood.iter().find(|s| s["9003"].as_u64() == Some(id)) {
+                merge(&mut light, setting);
+            }
+            if let Some(light) = light.as_object_mut() {
+                light.remove("9003");
+            }
+            if light.as_object().is_some_and(|l| l.is_empty()) {
+                continue;
+            }
+
+            let path = format!("15001/{}", id);
+            let Some(device) = self.resources.get_mut(&path) else { continue };
+            if device.get("3311").is_some() {
+                merge(device, &json!({ "3311": [light] }));
+            } else if let (Some(_), Some(on)) = (device.get("3312"), light.get("5850")) {
+                // Outlets only switch.
+                merge(device, &json!({ "3312": [{ "5850": on }] }));
+            } else {
+                continue;
+            }
+            changed.push(path);
+        }
+        changed
+    }
+}
+
+/// Merge `patch` into `target`: objects key by key, arrays element by element.
+fn merge(target: &mut Value, patch: &Value) {
+    match (target, patch) {
+        (Value::Object(target), Value::Object(patch)) => {
+            for (key, value) in patch {
+                merge(target.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+        (Value::Array(target), Value::Array(patch)) => {
+            for (i, value) in patch.iter().enumerate() {
+                match target.get_mut(i) {
+                    Some(existing) => merge(existing, value),
+                    None => target.push(value.clone()),
+                }
+            }
+        }
+        (target, patch) => *target = patch.clone(),
+    }
+}
+
+/// Gateway details as a freshly updated gateway reports them. `9059` (the
+/// gateway clock) is filled in on every GET unless set explicitly.
+fn default_gateway_info() -> Value {
+    json!({
+        "9081": "b8d7af2b1c3e", "9029": "1.21.31", "9054": 0, "9023": "pool.ntp.org",
+        "9061": 0, "9069": unix_now() - 2 * 365 * 86400
+    })
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+impl MemoryGateway {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // Every write leaves the documents valid JSON, so a panic mid-way is harmless.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Add a device document (`15001/<9003>`).
+    pub fn add_device(&self, device: Value) -> u64 {
+        self.lock().insert("15001", device)
+    }
+
+    /// Add a group document (`15004/<9003>`) with an empty mood list.
+    pub fn add_group(&self, group: Value) -> u64 {
+        let mut state = self.lock();
+        let id = state.insert("15004", group);
+        state.resources.insert(format!("15005/{}", id), json!([]));
+        id
+    }
+
+    /// Add a mood document (`15005/<group>/<9003>`).
+    pub fn add_mood(&self, group_id: u64, mood: Value) -> u64 {
+        self.lock().insert(&format!("15005/{}", group_id), mood)
+    }
+
+    /// Add a smart task document (`15010/<9003>`).
+    pub fn add_smart_task(&self, task: Value) -> u64 {
+        self.lock().insert(SMART_TASKS_PATH, task)
+    }
+
+    /// POST to a collection: store `payload` under a new ID, as the gateway
+    /// does when the app creates a room or mood.
+    fn create(&self, collection: &str, payload: &[u8], first_id: u64) -> Result<u64> {
+        let mut document: Value = serde_json::from_slice(payload).context("CoAP error BadRequest: invalid JSON")?;
+        let mut state = self.lock();
+        let id = state.next_id(first_id);
+        document["9003"] = id.into();
+        state.insert(collection, document);
+        state.notify(&[collection.to_string()]);
+        Ok(id)
+    }
+
+    /// Override gateway details (`15011/15012`), e.g. a stale `9059` clock
+    /// or an old `9029` firmware, to exercise the health warnings.
+    pub fn set_gateway_info(&self, patch: Value) {
+        let mut info = default_gateway_info();
+        merge(&mut info, &patch);
+        self.lock().resources.insert(GATEWAY_INFO_PATH.to_string(), info);
+    }
+
+    /// A small furnished home: bulbs of every kind, a plug, a blind, a
+    /// remote with a flat battery and a sensor, in three rooms with moods.
+    pub fn demo() -> Self {
+        let gateway = Self::default();
+        let now = unix_now();
+        let device = |id: u64, name: &str, kind: u8, model: &str, battery: Option<u8>, idle: u64| {
+            let mut info = json!({ "0": "IKEA of Sweden", "1": model, "3": "2.3.095", "6": 6 });
+            if let Some(battery) = battery {
+                info["6"] = 3.into();
+                info["9"] = battery.into();
+            }
+            json!({ "9003": id, "9001": name, "5750": kind, "9019": 1, "9020": now - idle, "3": info })
+        };
+
+        let mut taklampa = device(65537, "Taklampa", 2, "TRADFRI bulb E27 WS opal 1000lm", None, 4);
+        taklampa["3311"] = json!([{ "5850": 1, "5851": 200, "5711": 370, "5706": "f1e0b5" }]);
+        let mut golvlampa = device(65538, "Golvlampa", 2, "TRADFRI bulb E27 CWS opal 600lm", None, 9);
+        golvlampa["3311"] = json!([{
+            "5850": 1, "5851": 160, "5706": "8f2686",
+            "5707": 53953, "5708": 52200, "5709": 20316, "5710": 8520
+        }]);
+        let mut tv = device(65539, "TV-lampan", 2, "TRADFRI bulb E14 W op/ch 400lm", None, 31);
+        tv["3311"] = json!([{ "5850": 0, "5851": 80 }]);
+        let mut sang = device(65540, "Sänglampa", 2, "TRADFRI bulb E14 WS opal 400lm", None, 57);
+        sang["3311"] = json!([{ "5850": 0, "5851": 60, "5711": 454, "5706": "efd275" }]);
+        let mut kok = device(65541, "Köksbänk", 2, "TRADFRI bulb GU10 WS 400lm", None, 2);
+        kok["3311"] = json!([{ "5850": 1, "5851": 254, "5711": 250, "5706": "f5faf6" }]);
+        let mut julgran = device(65542, "Julgran", 3, "TRADFRI control outlet", None, 18);
+        julgran["3312"] = json!([{ "5850": 0 }]);
+        let mut gardin = device(65543, "Rullgardin", 7, "FYRTUR block-out roller blind", Some(64), 240);
+        gardin["15015"] = json!([{ "5536": 0.0 }]);
+        let mut remote = device(65544, "Fjärrkontroll", 0, "TRADFRI remote control", Some(12), 3600);
+        remote["15009"] = json!([{}]);
+        let mut sensor = device(65545, "Rörelsesensor", 4, "TRADFRI motion sensor", Some(35), 900);
+        sensor["3300"] = json!([{}]);
+        for device in [taklampa, golvlampa, tv, sang, kok, julgran, gardin, remote, sensor] {
+            gateway.add_device(device);
+        }
+        // Wake-up at 06:30 on weekdays in the bedroom.
+        gateway.add_smart_task(json!({
+            "9003": 327681, "5850": 1, "9040": 2, "9041": 31,
+            "9044": [{ "9046": 6, "9047": 30 }],
+            "9042": { "5850": 1, "15013": [{ "9003": 65540, "5851": 254, "5712": 18000 }] }
+        }));
+
+        let room = |id: u64, name: &str, members: &[u64]| {
+            json!({ "9003": id, "9001": name, "5850": 1, "5851": 200, "9018": { "15002": { "9003": members } } })
+        };
+        let vardagsrum = gateway.add_group(room(131073, "Vardagsrum", &[65537, 65538, 65539, 65542, 65543, 65544]));
+        gateway.add_group(room(131074, "Sovrummet", &[65540]));
+        gateway.add_group(room(131075, "Kök", &[65541, 65545]));
+
+        gateway.add_mood(vardagsrum, json!({ "9003": 196609, "9001": "Avkoppling", "15013": [
+            { "9003": 65537, "5850": 1, "5851": 90, "5711": 454 },
+            { "9003": 65538, "5850": 1, "5851": 60 },
+            { "9003": 65539, "5850": 1, "5851": 40 }
+        ]}));
+        gateway.add_mood(vardagsrum, json!({ "9003": 196610, "9001": "Fokus", "15013": [
+            { "9003": 65537, "5850": 1, "5851": 254, "5711": 250 },
+            { "9003": 65538, "5850": 0 },
+            { "9003": 65539, "5850": 0 }
+        ]}));
+        gateway
+    }
+}
+
+impl Transport for MemoryGateway {
+    fn get(&mut self, path: &str) -> Result<Vec<u8>> {
+        let state = self.lock();
+        if path == GATEWAY_INFO_PATH {
+            let mut info = state.resources.get(path).cloned().unwrap_or_else(default_gateway_info);
+            if info.get("9059").is_none() {
+                info["9059"] = unix_now().into();
+            }
+            return Ok(info.to_string().into_bytes());
+        }
+        if COLLECTIONS.contains(&path) && !state.resources.contains_key(path) {
+            return Ok(b"[]".to_vec());
+        }
+        let value = state
+            .resources
+            .get(path)
+            .with_context(|| format!("CoAP error NotFound: {}", path))?;
+        Ok(value.to_string().into_bytes())
+    }
+
+    fn put(&mut self, path: &str, payload: &[u8]) -> Result<()> {
+        let patch: Value = serde_json::from_slice(payload).context("CoAP error BadRequest: invalid JSON")?;
+        let mut state = self.lock();
+        if path == "15004/add" {
+            let group = state.add_members(&patch)?;
+            state.notify(&[group]);
+            return Ok(());
+        }
+        if !state.resources.contains_key(path) {
+            anyhow::bail!("CoAP error NotFound: {}", path);
+        }
+        let mut changed = vec![path.to_string()];
+        if let Some(group_id) = path.strip_prefix("15004/") {
+            changed.extend(state.apply_group(group_id, &patch));
+        }
+        if let Some(resource) = state.resources.get_mut(path) {
+            merge(resource, &patch);
+            // Mood settings are replaced as a whole, not merged light by light.
+            if let (true, Some(settings)) = (path.starts_with("15005/"), patch.get("15013")) {
+                resource["15013"] = settings.clone();
+            }
+        }
+        state.notify(&changed);
+        Ok(())
+    }
+
+    fn post(&mut self, path: &str, payload: &[u8]) -> Result<Vec<u8>> {
+        match path {
+            "15004" => {
+                let id = self.create(path, payload, FIRST_GROUP_ID)?;
+                self.lock().resources.insert(format!("15005/{}", id), json!([]));
+                Ok(Vec::new())
+            }
+            // A mood in an existing group's list: `15005/<group>`
+            _ if path.starts_with("15005/") && path.matches('/').count() == 1 && self.lock().resources.contains_key(path) => {
+                self.create(path, payload, FIRST_MOOD_ID).map(|_| Vec::new())
+            }
+            // Pairing: any identity is welcome.
+            "15011/9063" => Ok(json!({ "9091": "memory-gateway-psk" }).to_string().into_bytes()),
+            // Nothing to restart; state lives on as on the real gateway.
+            GATEWAY_REBOOT_PATH => Ok(Vec::new()),
+            _ => anyhow::bail!("CoAP error MethodNotAllowed: {}", path),
+        }
+    }
+
+    fn observe(
+        &mut self,
+        paths: &[String],
+        stop: &AtomicBool,
+        notify: &mut dyn FnMut(&str, &[u8]) -> bool,
+    ) -> Result<()> {
+        let (tx, rx) = mpsc::channel();
+        {
+            let mut state = self.lock();
+            for path in paths {
+                if let Some(value) = state.resources.get(path) {
+                    let _ = tx.send((path.clone(), value.to_string().into_bytes()));
+                }
+            }
+            state.observers.push((paths.to_vec(), tx));
+        }
+        while !stop.load(Ordering::Relaxed) {
+            match rx.recv_timeout(STOP_POLL) {
+                Ok((path, payload)) if !notify(&path, &payload) => break,
+                Ok(_) | Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1290 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::color;
+
+mod dtls;
+mod memory;
+
+use dtls::DtlsCoap;
+pub use dtls::RetryPolicy;
+pub use memory::MemoryGateway;
+
+/// Re-send Observe registrations after this long without any notification.
+/// Doubles as a liveness check: a dead session fails the re-registration.
+const OBSERVE_RESUBSCRIBE: Duration = Duration::from_secs(120);
+/// Consecutive failed observe sessions before giving up and falling back to polling.
+const OBSERVE_MAX_FAILURES: u32 = 5;
+/// Fixed DTLS identity the gateway accepts together with its security code.
+const PAIRING_IDENTITY: &str = "Client_identity";
+/// The gateway's own details: firmware, clock, NTP server.
+const GATEWAY_INFO_PATH: &str = "15011/15012";
+/// POST here to restart the gateway.
+const GATEWAY_REBOOT_PATH: &str = "15011/9030";
+/// Smart tasks: wake-up, "not at home" and on/off timers.
+const SMART_TASKS_PATH: &str = "15010";
+
+// ── Transport ───────────────────────────────────────────
+
+/// How the client talks to a gateway: CoAP over DTLS to real hardware, or an
+/// in-memory gateway for demos and tests. Paths are CoAP URIs without the
+/// leading slash (`15001/65537`); payloads are the gateway's JSON.
+pub trait Transport: Send {
+    fn get(&mut self, path: &str) -> Result<Vec<u8>>;
+
+    /// GET several paths. Backends that can keep requests in flight override
+    /// this; results are in the order of `paths`.
+    fn get_many(&mut self, paths: &[String]) -> Vec<Result<Vec<u8>>> {
+        paths.iter().map(|path| self.get(path)).collect()
+    }
+
+    fn put(&mut self, path: &str, payload: &[u8]) -> Result<()>;
+
+    fn post(&mut self, path: &str, payload: &[u8]) -> Result<Vec<u8>>;
+
+    /// Observe `paths`, calling `notify` with each notification's path and
+    /// payload, starting with the current state. Blocks until `notify`
+    /// returns `false` or `stop` is set (`Ok`), or the session fails (`Err`).
+    fn observe(
+        &mut self,
+        paths: &[String],
+        stop: &AtomicBool,
+        notify: &mut dyn FnMut(&str, &[u8]) -> bool,
+    ) -> Result<()>;
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn get(&mut self, path: &str) -> Result<Vec<u8>> {
+        (**self).get(path)
+    }
+
+    fn get_many(&mut self, paths: &[String]) -> Vec<Result<Vec<u8>>> {
+        (**self).get_many(paths)
+    }
+
+    fn put(&mut self, path: &str, payload: &[u8]) -> Result<()> {
+        (**self).put(path, payload)
+    }
+
+    fn post(&mut self, path: &str, payload: &[u8]) -> Result<Vec<u8>> {
+        (**self).post(path, payload)
+    }
+
+    fn observe(
+        &mut self,
+        paths: &[String],
+        stop: &AtomicBool,
+        notify: &mut dyn FnMut(&str, &[u8]) -> bool,
+    ) -> Result<()> {
+        (**self).observe(paths, stop, notify)
+    }
+}
+
+/// Where sessions come from. Cloned into background threads that open their
+/// own (observer, reconnects).
+#[derive(Clone)]
+pub enum Backend {
+    /// A real gateway over DTLS-PSK
+    Dtls {
+        host: String,
+        identity: String,
+        psk: String,
+        retry: RetryPolicy,
+    },
+    /// The in-memory gateway (`--demo`)
+    Memory(MemoryGateway),
+}
+
+impl Backend {
+    /// Open a new session.
+    pub fn connect(&self) -> Result<Box<dyn Transport>> {
+        match self {
+            Backend::Dtls { host, identity, psk, retry } => {
+                let coap = DtlsCoap::new(host, identity, psk, *retry)
+                    .context("Failed to connect to Trådfri gateway")?;
+                Ok(Box::new(coap))
+            }
+            Backend::Memory(gateway) => Ok(Box::new(gateway.clone())),
+        }
+    }
+}
+
+/// Light info parsed from Trådfri gateway response
+#[derive(Debug, Clone, Deserialize)]
+pub struct LightInfo {
+    pub id: u64,
+    pub name: String,
+    pub on: bool,
+    pub brightness: u8,
+    pub color_hex: Option<String>,
+    /// Hue, 0-65535 (colour bulbs only)
+    pub hue: Option<u16>,
+    /// Saturation, 0-65279 (colour bulbs only)
+    pub saturation: Option<u16>,
+    /// CIE xy, each 0-65535
+    pub color_xy: Option<(u16, u16)>,
+    /// Colour temperature in mireds (white spectrum bulbs)
+    pub mireds: Option<u16>,
+    pub reachable: bool,
+    /// Control outlet (`3312`) rather than a bulb: on/off only.
+    pub plug: bool,
+}
+
+/// Blind (FYRTUR/KADRILJ) info parsed from the `15015` list
+#[derive(Debug, Clone)]
+pub struct BlindInfo {
+    pub id: u64,
+    pub name: String,
+    /// 0 = fully open (up), 100 = fully closed (down)
+    pub position: u8,
+    /// Battery percentage from the device info object
+    pub battery: Option<u8>,
+    pub reachable: bool,
+}
+
+/// What a device is, from its application type (`5750`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceKind {
+    Light,
+    Outlet,
+    Blind,
+    Remote,
+    MotionSensor,
+    Repeater,
+    /// Application type FrostLux doesn't know
+    Other(u32),
+}
+
+impl DeviceKind {
+    fn from_application_type(code: u32) -> Self {
+        match code {
+            0 | 1 => DeviceKind::Remote,
+            2 => DeviceKind::Light,
+            3 => DeviceKind::Outlet,
+            4 => DeviceKind::MotionSensor,
+            6 => DeviceKind::Repeater,
+            7 => DeviceKind::Blind,
+            other => DeviceKind::Other(other),
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DeviceKind::Light => "light",
+            DeviceKind::Outlet => "outlet",
+            DeviceKind::Blind => "blind",
+            DeviceKind::Remote => "remote",
+            DeviceKind::MotionSensor => "motion sensor",
+            DeviceKind::Repeater => "repeater",
+            DeviceKind::Other(_) => "device",
+        }
+    }
+}
+
+/// How a device is powered (`3`/`6`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerSource {
+    Mains,
+    Battery,
+    Usb,
+    Unknown,
+}
+
+impl PowerSource {
+    fn from_code(code: u32) -> Self {
+        match code {
+            1..=3 => PowerSource::Battery,
+            5 => PowerSource::Usb,
+            4 | 6 => PowerSource::Mains,
+            _ => PowerSource::Unknown,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PowerSource::Mains => "mains",
+            PowerSource::Battery => "battery",
+            PowerSource::Usb => "USB",
+            PowerSource::Unknown => "unknown",
+        }
+    }
+}
+
+/// Any paired device, from its `3` device-info object
+#[derive(Debug, Clone)]
+pub struct DeviceInfo {
+    pub id: u64,
+    pub name: String,
+    pub kind: DeviceKind,
+    pub manufacturer: String,
+    pub model: String,
+    pub firmware: String,
+    pub power_source: PowerSource,
+    /// Battery percentage (battery-powered devices only)
+    pub battery: Option<u8>,
+    pub reachable: bool,
+    /// When the gateway last heard from the device (Unix seconds)
+    pub last_seen: Option<u64>,
+}
+
+/// State change pushed by the observer thread.
+#[derive(Debug)]
+pub enum ObserveEvent {
+    /// Fresh state for an observed light.
+    Light(LightInfo),
+    /// Fresh state for an observed blind.
+    Blind(BlindInfo),
+    /// The observer gave up; the caller should fall back to polling.
+    Ended(String),
+}
+
+/// Group (room) info parsed from `15004/<id>`
+#[derive(Debug, Clone)]
+pub struct GroupInfo {
+    pub id: u64,
+    pub name: String,
+    /// Active mood ID, if any
+    pub mood_id: Option<u64>,
+    /// Member device IDs (lights, but also remotes and sensors)
+    pub member_ids: Vec<u64>,
+}
+
+/// Gateway mood (native scene) parsed from `15005/<group>/<mood>`
+#[derive(Debug, Clone)]
+pub struct MoodInfo {
+    pub id: u64,
+    pub group_id: u64,
+    pub name: String,
+}
+
+/// The gateway itself, parsed from `15011/15012`
+#[derive(Debug, Clone)]
+pub struct GatewayInfo {
+    /// Gateway ID (its MAC address)
+    pub id: String,
+    pub firmware: String,
+    /// True when the gateway reports a firmware update waiting (`9054`)
+    pub update_available: bool,
+    /// The gateway's clock (Unix seconds)
+    pub time: Option<u64>,
+    pub ntp_server: String,
+    /// True while the gateway accepts new devices (`9061`)
+    pub commissioning: bool,
+    /// When the gateway was first set up (Unix seconds)
+    pub first_setup: Option<u64>,
+}
+
+/// Everything the TUI shows, fetched in one pass.
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    /// Every paired device, including remotes and sensors
+    pub devices: Vec<DeviceInfo>,
+    pub lights: Vec<LightInfo>,
+    pub blinds: Vec<BlindInfo>,
+    pub groups: Vec<GroupInfo>,
+    pub moods: Vec<MoodInfo>,
+}
+
+/// Every resource as the gateway's own JSON, for backups.
+#[derive(Debug, Clone)]
+pub struct HomeDocuments {
+    /// `15011/15012`
+    pub gateway: serde_json::Value,
+    pub devices: Vec<serde_json::Value>,
+    pub groups: Vec<serde_json::Value>,
+    /// Moods by group ID
+    pub moods: BTreeMap<u64, Vec<serde_json::Value>>,
+    pub smart_tasks: Vec<serde_json::Value>,
+}
+
+/// Raw Trådfri device JSON (keys are CoAP resource numbers)
+#[derive(Debug, Deserialize)]
+struct TradfriDevice {
+    /// Light list (array of bulbs)
+    #[serde(rename = "3311")]
+    bulbs: Option<Vec<TradfriLightBulb>>,
+    /// Control outlet list (smart plugs)
+    #[serde(rename = "3312")]
+    plugs: Option<Vec<TradfriPlug>>,
+    /// Blind list
+    #[serde(rename = "15015")]
+    blinds: Option<Vec<TradfriBlind>>,
+    /// Switch list (remotes); only its presence is used
+    #[serde(rename = "15009")]
+    switches: Option<serde_json::Value>,
+    /// Sensor list (motion sensors); only its presence is used
+    #[serde(rename = "3300")]
+    sensors: Option<serde_json::Value>,
+    /// Application type (0 remote, 2 light, 3 outlet, 4 sensor, 6 repeater, 7 blind)
+    #[serde(rename = "5750")]
+    application_type: Option<u32>,
+    /// Device info (manufacturer, battery, ...)
+    #[serde(rename = "3")]
+    info: Option<TradfriDeviceInfo>,
+    /// Device name
+    #[serde(rename = "9001")]
+    name: String,
+    /// Instance ID
+    #[serde(rename = "9003")]
+    id: u64,
+    /// Reachable (1 = true, 0 = false)
+    #[serde(rename = "9019")]
+    reachable: Option<u32>,
+    /// Last seen (Unix seconds)
+    #[serde(rename = "9020")]
+    last_seen: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TradfriLightBulb {
+    /// Color hex (e.g. "f1e0b5")
+    #[serde(rename = "5706")]
+    color_hex: Option<String>,
+    /// On/Off (1/0)
+    #[serde(rename = "5850")]
+    on: Option<u32>,
+    /// Brightness (0-254)
+    #[serde(rename = "5851")]
+    brightness: Option<u8>,
+    /// Hue (0-65535), colour bulbs only
+    #[serde(rename = "5707")]
+    hue: Option<u16>,
+    /// Saturation (0-65279), colour bulbs only
+    #[serde(rename = "5708")]
+    saturation: Option<u16>,
+    /// CIE x (0-65535)
+    #[serde(rename = "5709")]
+    color_x: Option<u16>,
+    /// CIE y (0-65535)
+    #[serde(rename = "5710")]
+    color_y: Option<u16>,
+    /// Colour temperature in mireds
+    #[serde(rename = "5711")]
+    mireds: Option<u16>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TradfriPlug {
+    /// On/Off (1/0)
+    #[serde(rename = "5850")]
+    on: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TradfriBlind {
+    /// Position, 0 (open) - 100 (closed); some firmware sends a float
+    #[serde(rename = "5536")]
+    position: Option<f32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct TradfriDeviceInfo {
+    /// Manufacturer
+    #[serde(rename = "0")]
+    manufacturer: Option<String>,
+    /// Model number (e.g. "TRADFRI bulb E27 WS opal 980lm")
+    #[serde(rename = "1")]
+    model: Option<String>,
+    /// Firmware version
+    #[serde(rename = "3")]
+    firmware: Option<String>,
+    /// Power source (1-3 battery, 4 PoE, 5 USB, 6 AC, 7 solar)
+    #[serde(rename = "6")]
+    power_source: Option<u32>,
+    /// Battery level in percent (battery-powered devices only)
+    #[serde(rename = "9")]
+    battery: Option<u8>,
+}
+
+/// Raw Trådfri gateway JSON (`15011/15012`)
+#[derive(Debug, Deserialize)]
+struct TradfriGateway {
+    /// Gateway ID
+    #[serde(rename = "9081")]
+    id: Option<String>,
+    /// Firmware version
+    #[serde(rename = "9029")]
+    firmware: Option<String>,
+    /// OTA update state (0 = up to date)
+    #[serde(rename = "9054")]
+    update_state: Option<u32>,
+    /// Current time (Unix seconds)
+    #[serde(rename = "9059")]
+    time: Option<u64>,
+    /// NTP server
+    #[serde(rename = "9023")]
+    ntp_server: Option<String>,
+    /// Commissioning mode (non-zero while open for pairing devices)
+    #[serde(rename = "9061")]
+    commissioning: Option<u64>,
+    /// First setup (Unix seconds)
+    #[serde(rename = "9069")]
+    first_setup: Option<u64>,
+}
+
+impl From<TradfriGateway> for GatewayInfo {
+    fn from(gateway: TradfriGateway) -> Self {
+        Self {
+            id: gateway.id.unwrap_or_default(),
+            firmware: gateway.firmware.unwrap_or_default(),
+            update_available: gateway.update_state.is_some_and(|s| s != 0),
+            time: gateway.time.filter(|&t| t != 0),
+            ntp_server: gateway.ntp_server.unwrap_or_default(),
+            commissioning: gateway.commissioning.is_some_and(|c| c != 0),
+            first_setup: gateway.first_setup.filter(|&t| t != 0),
+        }
+    }
+}
+
+/// Raw Trådfri group JSON
+#[derive(Debug, Deserialize)]
+struct TradfriGroup {
+    /// Group name
+    #[serde(rename = "9001")]
+    name: String,
+    /// Instance ID
+    #[serde(rename = "9003")]
+    id: u64,
+    /// Active mood ID
+    #[serde(rename = "9039")]
+    mood_id: Option<u64>,
+    /// Members: `{"15002": {"9003": [ids]}}`
+    #[serde(rename = "9018")]
+    members: Option<TradfriGroupMembers>,
+}
+
+/// Raw Trådfri mood JSON
+#[derive(Debug, Deserialize)]
+struct TradfriMood {
+    /// Mood name
+    #[serde(rename = "9001")]
+    name: String,
+    /// Instance ID
+    #[serde(rename = "9003")]
+    id: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TradfriGroupMembers {
+    #[serde(rename = "15002")]
+    devices: Option<TradfriIdList>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TradfriIdList {
+    #[serde(rename = "9003")]
+    ids: Vec<u64>,
+}
+
+impl From<TradfriGroup> for GroupInfo {
+    fn from(group: TradfriGroup) -> Self {
+        Self {
+            id: group.id,
+            name: group.name,
+            mood_id: group.mood_id.filter(|&m| m != 0),
+            member_ids: group
+                .members
+                .and_then(|m| m.devices)
+                .map(|d| d.ids)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl TradfriDevice {
+    /// What the device is: from its application type (`5750`), or guessed
+    /// from its resource lists when older firmware leaves that out.
+    fn kind(&self) -> DeviceKind {
+        if let Some(code) = self.application_type {
+            return DeviceKind::from_application_type(code);
+        }
+        // Older firmware leaves out 5750; go by which resource list exists.
+        if self.bulbs.is_some() {
+            DeviceKind::Light
+        } else if self.plugs.is_some() {
+            DeviceKind::Outlet
+        } else if self.blinds.is_some() {
+            DeviceKind::Blind
+        } else if self.switches.is_some() {
+            DeviceKind::Remote
+        } else if self.sensors.is_some() {
+            DeviceKind::MotionSensor
+        } else {
+            DeviceKind::Other(u32::MAX)
+        }
+    }
+
+    fn device_info(&self) -> DeviceInfo {
+        let info = self.info.as_ref();
+        let text = |field: fn(&TradfriDeviceInfo) -> &Option<String>| {
+            info.and_then(|i| field(i).clone()).unwrap_or_default()
+        };
+        DeviceInfo {
+            id: self.id,
+            name: self.name.clone(),
+            kind: self.kind(),
+            manufacturer: text(|i| &i.manufacturer),
+            model: text(|i| &i.model),
+            firmware: text(|i| &i.firmware),
+            power_source: info
+                .and_then(|i| i.power_source)
+                .map_or(PowerSource::Unknown, PowerSource::from_code),
+            battery: info.and_then(|i| i.battery),
+            reachable: self.reachable.unwrap_or(0) == 1,
+            last_seen: self.last_seen,
+        }
+    }
+
+    fn into_blind_info(self) -> Option<BlindInfo> {
+        let blind = self.blinds.as_ref().and_then(|b| b.first())?;
+        Some(BlindInfo {
+            id: self.id,
+            position: blind.position.unwrap_or(0.0).round().clamp(0.0, 100.0) as u8,
+            battery: self.info.as_ref().and_then(|i| i.battery),
+            reachable: self.reachable.unwrap_or(0) == 1,
+            name: self.name,
+        })
+    }
+
+    /// Push-update event for lights, plugs and blinds; `None` for the rest.
+    fn into_event(self) -> Option<ObserveEvent> {
+        if self.blinds.is_some() {
+            return self.into_blind_info().map(ObserveEvent::Blind);
+        }
+        self.into_light_info().map(ObserveEvent::Light)
+    }
+
+    /// Bulbs and control outlets; `None` for remotes, sensors etc.
+    fn into_light_info(self) -> Option<LightInfo> {
+        if let Some(plug) = self.plugs.as_ref().and_then(|p| p.first()) {
+            let on = plug.on.unwrap_or(0) == 1;
+            return Some(LightInfo {
+                id: self.id,
+                on,
+                brightness: if on { 254 } else { 0 },
+                color_hex: None,
+                hue: None,
+                saturation: None,
+                color_xy: None,
+                mireds: None,
+                reachable: self.reachable.unwrap_or(0) == 1,
+                plug: true,
+                name: self.name,
+            });
+        }
+        let bulb = self.bulbs.as_ref().and_then(|b| b.first())?;
+        Some(LightInfo {
+            id: self.id,
+            on: bulb.on.unwrap_or(0) == 1,
+            brightness: bulb.brightness.unwrap_or(0),
+            color_hex: bulb.color_hex.clone(),
+            hue: bulb.hue,
+            saturation: bulb.saturation,
+            color_xy: bulb.color_x.zip(bulb.color_y),
+            mireds: bulb.mireds,
+            reachable: self.reachable.unwrap_or(0) == 1,
+            plug: false,
+            name: self.name,
+        })
+    }
+}
+
+/// Trådfri client over a persistent session of any `Transport`.
+pub struct TradfriClient<T: Transport = Box<dyn Transport>> {
+    transport: T,
+}
+
+impl<T: Transport> TradfriClient<T> {
+    pub fn new(transport: T) -> Self {
+        Self { transport }
+    }
+
+    /// GET the ID list at `collection`, then every entry in it, pipelined.
+    fn get_collection(&mut self, collection: &str) -> Result<Vec<(u64, Result<Vec<u8>>)>> {
+        let payload = self.transport.get(collection)?;
+        let ids: Vec<u64> = serde_json::from_slice(&payload)
+            .with_context(|| format!("Failed to parse ID list at {}", collection))?;
+        let paths: Vec<String> = ids.iter().map(|id| format!("{}/{}", collection, id)).collect();
+        Ok(ids.into_iter().zip(self.transport.get_many(&paths)).collect())
+    }
+
+    /// Fetch every device the gateway knows, skipping ones that fail.
+    fn list_devices(&mut self) -> Result<Vec<TradfriDevice>> {
+        let mut devices = Vec::new();
+        for (id, payload) in self.get_collection("15001")? {
+            let Ok(payload) = payload else {
+                tracing::warn!("skipped device {}: GET failed", id);
+                continue;
+            };
+            let Ok(device) = serde_json::from_slice::<TradfriDevice>(&payload) else {
+                tracing::warn!("skipped device {}: JSON parse failed", id);
+                continue;
+            };
+            devices.push(device);
+        }
+        Ok(devices)
+    }
+
+    /// List all lights (bulbs and control outlets) from the gateway
+    pub fn list_lights(&mut self) -> Result<Vec<LightInfo>> {
+        Ok(self
+            .list_devices()?
+            .into_iter()
+            .filter_map(TradfriDevice::into_light_info)
+            .collect())
+    }
+
+    /// Firmware, clock and NTP settings of the gateway itself
+    pub fn gateway_info(&mut self) -> Result<GatewayInfo> {
+        let payload = self.transport.get(GATEWAY_INFO_PATH)?;
+        let gateway: TradfriGateway =
+            serde_json::from_slice(&payload).context("Failed to parse gateway info")?;
+        Ok(GatewayInfo::from(gateway))
+    }
+
+    /// List all blinds from the gateway
+    pub fn list_blinds(&mut self) -> Result<Vec<BlindInfo>> {
+        Ok(self
+            .list_devices()?
+            .into_iter()
+            .filter_map(TradfriDevice::into_blind_info)
+            .collect())
+    }
+
+    /// List all groups (rooms) from the gateway
+    pub fn list_groups(&mut self) -> Result<Vec<GroupInfo>> {
+        let mut groups = Vec::new();
+        for (id, payload) in self.get_collection("15004")? {
+            let Ok(payload) = payload else {
+                tracing::warn!("skipped group {}: GET failed", id);
+                continue;
+            };
+            match serde_json::from_slice::<TradfriGroup>(&payload) {
+                Ok(group) => groups.push(GroupInfo::from(group)),
+                Err(e) => tracing::warn!("skipped group {}: JSON parse failed: {}", id, e),
+            }
+        }
+        Ok(groups)
+    }
+
+    /// List the moods stored for one group
+    pub fn list_moods(&mut self, group_id: u64) -> Result<Vec<MoodInfo>> {
+        let mut moods = Vec::new();
+        for (id, payload) in self.get_collection(&format!("15005/{}", group_id))? {
+            let Ok(payload) = payload else {
+                tracing::warn!("skipped mood {}/{}: GET failed", group_id, id);
+                continue;
+            };
+            match serde_json::from_slice::<TradfriMood>(&payload) {
+                Ok(mood) => moods.push(MoodInfo {
+                    id: mood.id,
+                    group_id,
+                    name: mood.name,
+                }),
+                Err(e) => tracing::warn!("skipped mood {}/{}: JSON parse failed: {}", group_id, id, e),
+            }
+        }
+        Ok(moods)
+    }
+
+    /// Every document in `collection`. Unlike the listings above, a document
+    /// that fails is an error: a backup must not silently miss one.
+    fn documents(&mut self, collection: &str) -> Result<Vec<serde_json::Value>> {
+        self.get_collection(collection)?
+            .into_iter()
+            .map(|(id, payload)| {
+                let payload = payload.with_context(|| format!("Failed to fetch {}/{}", collection, id))?;
+                serde_json::from_slice(&payload).with_context(|| format!("Failed to parse {}/{}", collection, id))
+            })
+            .collect()
+    }
+
+    /// Devices, groups, moods, smart tasks and gateway details, unparsed.
+    pub fn home_documents(&mut self) -> Result<HomeDocuments> {
+        let gateway = serde_json::from_slice(&self.transport.get(GATEWAY_INFO_PATH)?)
+            .context("Failed to parse gateway info")?;
+        let devices = self.documents("15001")?;
+        let groups = self.documents("15004")?;
+        let mut moods = BTreeMap::new();
+        for id in groups.iter().filter_map(|g| g["9003"].as_u64()) {
+            moods.insert(id, self.documents(&format!("15005/{}", id))?);
+        }
+        let smart_tasks = self.documents(SMART_TASKS_PATH)?;
+        Ok(HomeDocuments { gateway, devices, groups, moods, smart_tasks })
+    }
+
+    /// Fetch lights, groups and every group's moods.
+    pub fn snapshot(&mut self) -> Result<Snapshot> {
+        let mut devices = Vec::new();
+        let mut lights = Vec::new();
+        let mut blinds = Vec::new();
+        for device in self.list_devices()? {
+            devices.push(device.device_info());
+            match device.into_event() {
+                Some(ObserveEvent::Light(light)) => lights.push(light),
+                Some(ObserveEvent::Blind(blind)) => blinds.push(blind),
+                _ => {}
+            }
+        }
+        let groups = self.list_groups().unwrap_or_else(|e| {
+            tracing::warn!("failed to list groups: {}", e);
+            Vec::new()
+        });
+        let mut moods = Vec::new();
+        for group in &groups {
+            match self.list_moods(group.id) {
+                Ok(group_moods) => moods.extend(group_moods),
+                Err(e) => tracing::warn!("failed to list moods for group {}: {}", group.id, e),
+            }
+        }
+        Ok(Snapshot { devices, lights, blinds, groups, moods })
+    }
+
+    /// PUT a state change to one light, fading over `transition` when set.
+    fn put_light(
+        &mut self,
+        id: u64,
+        mut state: serde_json::Value,
+        transition: Option<Duration>,
+    ) -> Result<()> {
+        insert_transition(&mut state, transition);
+        let payload = serde_json::json!({ "3311": [state] });
+        self.transport
+            .put(&format!("15001/{}", id), payload.to_string().as_bytes())
+    }
+
+    /// PUT a state change to one control outlet.
+    fn put_plug(&mut self, id: u64, state: serde_json::Value) -> Result<()> {
+        let payload = serde_json::json!({ "3312": [state] });
+        self.transport
+            .put(&format!("15001/{}", id), payload.to_string().as_bytes())
+    }
+
+    /// PUT a state change to one blind.
+    fn put_blind(&mut self, id: u64, state: serde_json::Value) -> Result<()> {
+        let payload = serde_json::json!({ "15015": [state] });
+        self.transport
+            .put(&format!("15001/{}", id), payload.to_string().as_bytes())
+    }
+
+    /// PUT a state change to every light in a group with one request.
+    fn put_group(
+        &mut self,
+        id: u64,
+        mut state: serde_json::Value,
+        transition: Option<Duration>,
+    ) -> Result<()> {
+        insert_transition(&mut state, transition);
+        self.transport
+            .put(&format!("15004/{}", id), state.to_string().as_bytes())
+    }
+
+    /// Set power on/off for a light.
+    pub fn set_power(&mut self, id: u64, on: bool, transition: Option<Duration>) -> Result<()> {
+        self.put_light(id, serde_json::json!({"5850": on as u8}), transition)
+    }
+
+    /// Switch a control outlet on/off. Outlets don't fade.
+    pub fn set_plug_power(&mut self, id: u64, on: bool) -> Result<()> {
+        self.put_plug(id, serde_json::json!({"5850": on as u8}))
+    }
+
+    /// Move a blind to `position` (0 = open, 100 = closed).
+    pub fn set_blind_position(&mut self, id: u64, position: u8) -> Result<()> {
+        self.put_blind(id, serde_json::json!({"5536": position.min(100)}))
+    }
+
+    /// Stop a moving blind where it is.
+    pub fn stop_blind(&mut self, id: u64) -> Result<()> {
+        self.put_blind(id, serde_json::json!({"5523": 0}))
+    }
+
+    /// Set brightness (0-254)
+    pub fn set_brightness(
+        &mut self,
+        id: u64,
+        brightness: u8,
+        transition: Option<Duration>,
+    ) -> Result<()> {
+        let state = serde_json::json!({"5851": brightness, "5850": (brightness > 0) as u8});
+        self.put_light(id, state, transition)
+    }
+
+    /// Set color temperature by hex value
+    pub fn set_color(&mut self, id: u64, hex: &str, transition: Option<Duration>) -> Result<()> {
+        self.put_light(id, serde_json::json!({"5706": hex}), transition)
+    }
+
+    /// Set colour temperature in mireds (white spectrum bulbs).
+    pub fn set_mireds(
+        &mut self,
+        id: u64,
+        mireds: u16,
+        transition: Option<Duration>,
+    ) -> Result<()> {
+        self.put_light(id, serde_json::json!({"5711": mireds}), transition)
+    }
+
+    /// Set an arbitrary colour as CIE xy (each 0.0-1.0). Colour bulbs only.
+    pub fn set_xy(
+        &mut self,
+        id: u64,
+        x: f32,
+        y: f32,
+        transition: Option<Duration>,
+    ) -> Result<()> {
+        let (x, y) = color::xy_to_raw(x, y);
+        self.put_light(id, serde_json::json!({"5709": x, "5710": y}), transition)
+    }
+
+    /// Set an arbitrary sRGB colour. Colour bulbs only; brightness is unchanged.
+    pub fn set_rgb(
+        &mut self,
+        id: u64,
+        (r, g, b): (u8, u8, u8),
+        transition: Option<Duration>,
+    ) -> Result<()> {
+        let (x, y) = color::rgb_to_xy(r, g, b);
+        self.set_xy(id, x, y, transition)
+    }
+
+    /// Apply a scene (set brightness + colour temperature + on/off for a light)
+    pub fn apply_scene_to_light(
+        &mut self,
+        id: u64,
+        state: SceneState,
+        transition: Option<Duration>,
+    ) -> Result<()> {
+        self.put_light(id, state.to_json(), transition)
+    }
+
+    /// Set power on/off for every light in a group with one request.
+    pub fn set_group_power(
+        &mut self,
+        id: u64,
+        on: bool,
+        transition: Option<Duration>,
+    ) -> Result<()> {
+        self.put_group(id, serde_json::json!({"5850": on as u8}), transition)
+    }
+
+    /// Set brightness (0-254) for every light in a group.
+    pub fn set_group_brightness(
+        &mut self,
+        id: u64,
+        brightness: u8,
+        transition: Option<Duration>,
+    ) -> Result<()> {
+        let state = serde_json::json!({"5851": brightness, "5850": (brightness > 0) as u8});
+        self.put_group(id, state, transition)
+    }
+
+    /// Restart the gateway. It drops every session and is back after about
+    /// a minute; devices keep their state meanwhile.
+    pub fn reboot_gateway(&mut self) -> Result<()> {
+        self.transport.post(GATEWAY_REBOOT_PATH, &[]).map(drop)
+    }
+
+    /// Rename a device (light, plug, blind, remote, sensor).
+    pub fn rename_device(&mut self, id: u64, name: &str) -> Result<()> {
+        let payload = serde_json::json!({ "9001": name });
+        self.transport
+            .put(&format!("15001/{}", id), payload.to_string().as_bytes())
+    }
+
+    /// Rename a group (room).
+    pub fn rename_group(&mut self, id: u64, name: &str) -> Result<()> {
+        let payload = serde_json::json!({ "9001": name });
+        self.transport
+            .put(&format!("15004/{}", id), payload.to_string().as_bytes())
+    }
+
+    /// Create a group (room) with `member_ids`.
+    pub fn create_group(&mut self, name: &str, member_ids: &[u64]) -> Result<()> {
+        let payload = serde_json::json!({ "9001": name, "9018": { "15002": { "9003": member_ids } } });
+        self.transport.post("15004", payload.to_string().as_bytes()).map(drop)
+    }
+
+    /// Add devices to an existing group.
+    pub fn add_group_members(&mut self, id: u64, member_ids: &[u64]) -> Result<()> {
+        let payload = serde_json::json!({ "9003": id, "9018": { "15002": { "9003": member_ids } } });
+        self.transport.put("15004/add", payload.to_string().as_bytes())
+    }
+
+    /// Create a mood in a group; `settings` are per-light states (`15013`).
+    pub fn create_mood(&mut self, group_id: u64, name: &str, settings: &[serde_json::Value]) -> Result<()> {
+        let payload = serde_json::json!({ "9001": name, "15013": settings });
+        self.transport
+            .post(&format!("15005/{}", group_id), payload.to_string().as_bytes())
+            .map(drop)
+    }
+
+    /// Replace the per-light states of a mood.
+    pub fn set_mood_settings(&mut self, group_id: u64, mood_id: u64, settings: &[serde_json::Value]) -> Result<()> {
+        let payload = serde_json::json!({ "15013": settings });
+        self.transport
+            .put(&format!("15005/{}/{}", group_id, mood_id), payload.to_string().as_bytes())
+    }
+
+    /// Activate a gateway mood on a group.
+    pub fn set_group_mood(
+        &mut self,
+        id: u64,
+        mood_id: u64,
+        transition: Option<Duration>,
+    ) -> Result<()> {
+        self.put_group(id, serde_json::json!({"9039": mood_id, "5850": 1}), transition)
+    }
+
+    /// Apply a scene to a whole group with a single request.
+    pub fn apply_scene_to_group(
+        &mut self,
+        id: u64,
+        state: SceneState,
+        transition: Option<Duration>,
+    ) -> Result<()> {
+        self.put_group(id, state.to_json(), transition)
+    }
+}
+
+/// Target state written by a scene: power, brightness and colour temperature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SceneState {
+    pub on: bool,
+    pub brightness: u8,
+    /// Left out for bulbs without colour temperature.
+    pub mireds: Option<u16>,
+}
+
+impl SceneState {
+    fn to_json(self) -> serde_json::Value {
+        let mut state = serde_json::json!({
+            "5850": self.on as u8,
+            "5851": self.brightness
+        });
+        if let Some(mireds) = self.mireds {
+            state["5711"] = mireds.into();
+        }
+        state
+    }
+}
+
+/// Add `5712` (transition time in tenths of a second) to a state object.
+fn insert_transition(state: &mut serde_json::Value, transition: Option<Duration>) {
+    if let (Some(transition), Some(obj)) = (transition, state.as_object_mut()) {
+        obj.insert("5712".into(), transition_tenths(transition).into());
+    }
+}
+
+/// Duration → the gateway's `5712` unit, rounded to the nearest tenth.
+fn transition_tenths(transition: Duration) -> u64 {
+    (transition.as_millis() as u64 + 50) / 100
+}
+
+/// Thread-safe wrapper for TradfriClient
+#[derive(Clone)]
+pub struct SharedTradfriClient {
+    inner: Arc<Mutex<TradfriClient>>,
+}
+
+impl From<TradfriClient> for SharedTradfriClient {
+    fn from(client: TradfriClient) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(client)),
+        }
+    }
+}
+
+impl SharedTradfriClient {
+    pub fn new(backend: &Backend) -> Result<Self> {
+        Ok(Self::from(TradfriClient::new(backend.connect()?)))
+    }
+
+    fn lock_client(&self) -> Result<std::sync::MutexGuard<'_, TradfriClient>> {
+        self.inner
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Gateway client lock poisoned"))
+    }
+
+    pub fn list_lights(&self) -> Result<Vec<LightInfo>> {
+        self.lock_client()?.list_lights()
+    }
+
+    pub fn set_power(&self, id: u64, on: bool, transition: Option<Duration>) -> Result<()> {
+        self.lock_client()?.set_power(id, on, transition)
+    }
+
+    pub fn set_plug_power(&self, id: u64, on: bool) -> Result<()> {
+        self.lock_client()?.set_plug_power(id, on)
+    }
+
+    pub fn list_blinds(&self) -> Result<Vec<BlindInfo>> {
+        self.lock_client()?.list_blinds()
+    }
+
+    pub fn set_blind_position(&self, id: u64, position: u8) -> Result<()> {
+        self.lock_client()?.set_blind_position(id, position)
+    }
+
+    pub fn stop_blind(&self, id: u64) -> Result<()> {
+        self.lock_client()?.stop_blind(id)
+    }
+
+    pub fn set_brightness(
+        &self,
+        id: u64,
+        brightness: u8,
+        transition: Option<Duration>,
+    ) -> Result<()> {
+        self.lock_client()?.set_brightness(id, brightness, transition)
+    }
+
+    pub fn set_color(&self, id: u64, hex: &str, transition: Option<Duration>) -> Result<()> {
+        self.lock_client()?.set_color(id, hex, transition)
+    }
+
+    pub fn set_rgb(
+        &self,
+        id: u64,
+        rgb: (u8, u8, u8),
+        transition: Option<Duration>,
+    ) -> Result<()> {
+        self.lock_client()?.set_rgb(id, rgb, transition)
+    }
+
+    /// Set colour temperature in Kelvin.
+    pub fn set_color_temp_kelvin(
+        &self,
+        id: u64,
+        kelvin: u16,
+        transition: Option<Duration>,
+    ) -> Result<()> {
+        self.lock_client()?
+            .set_mireds(id, color::kelvin_to_mireds(kelvin), transition)
+    }
+
+    pub fn apply_scene_to_light(
+        &self,
+        id: u64,
+        state: SceneState,
+        transition: Option<Duration>,
+    ) -> Result<()> {
+        self.lock_client()?.apply_scene_to_light(id, state, transition)
+    }
+
+    pub fn list_groups(&self) -> Result<Vec<GroupInfo>> {
+        self.lock_client()?.list_groups()
+    }
+
+    pub fn set_group_power(&self, id: u64, on: bool, transition: Option<Duration>) -> Result<()> {
+        self.lock_client()?.set_group_power(id, on, transition)
+    }
+
+    pub fn set_group_brightness(
+        &self,
+        id: u64,
+        brightness: u8,
+        transition: Option<Duration>,
+    ) -> Result<()> {
+        self.lock_client()?
+            .set_group_brightness(id, brightness, transition)
+    }
+
+    pub fn list_moods(&self, group_id: u64) -> Result<Vec<MoodInfo>> {
+        self.lock_client()?.list_moods(group_id)
+    }
+
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        self.lock_client()?.snapshot()
+    }
+
+    pub fn gateway_info(&self) -> Result<GatewayInfo> {
+        self.lock_client()?.gateway_info()
+    }
+
+    pub fn home_documents(&self) -> Result<HomeDocuments> {
+        self.lock_client()?.home_documents()
+    }
+
+    pub fn reboot_gateway(&self) -> Result<()> {
+        self.lock_client()?.reboot_gateway()
+    }
+
+    pub fn rename_device(&self, id: u64, name: &str) -> Result<()> {
+        self.lock_client()?.rename_device(id, name)
+    }
+
+    pub fn rename_group(&self, id: u64, name: &str) -> Result<()> {
+        self.lock_client()?.rename_group(id, name)
+    }
+
+    pub fn create_group(&self, name: &str, member_ids: &[u64]) -> Result<()> {
+        self.lock_client()?.create_group(name, member_ids)
+    }
+
+    pub fn add_group_members(&self, id: u64, member_ids: &[u64]) -> Result<()> {
+        self.lock_client()?.add_group_members(id, member_ids)
+    }
+
+    pub fn create_mood(&self, group_id: u64, name: &str, settings: &[serde_json::Value]) -> Result<()> {
+        self.lock_client()?.create_mood(group_id, name, settings)
+    }
+
+    pub fn set_mood_settings(&self, group_id: u64, mood_id: u64, settings: &[serde_json::Value]) -> Result<()> {
+        self.lock_client()?.set_mood_settings(group_id, mood_id, settings)
+    }
+
+    pub fn set_group_mood(
+        &self,
+        id: u64,
+        mood_id: u64,
+        transition: Option<Duration>,
+    ) -> Result<()> {
+        self.lock_client()?.set_group_mood(id, mood_id, transition)
+    }
+
+    pub fn apply_scene_to_group(
+        &self,
+        id: u64,
+        state: SceneState,
+        transition: Option<Duration>,
+    ) -> Result<()> {
+        self.lock_client()?.apply_scene_to_group(id, state, transition)
+    }
+}
+
+/// Connect to the gateway, fetch lights, groups and moods, and return them
+/// together with a ready-to-use persistent client — all over a single DTLS
+/// connection.
+///
+/// One handshake, pipelined device requests. The Trådfri gateway handles
+/// concurrent DTLS sessions poorly, so several requests in flight on a single
+/// connection are faster in practice than a parallel pool.
+pub fn connect_and_fetch(backend: &Backend) -> Result<(Snapshot, SharedTradfriClient)> {
+    let mut client = TradfriClient::new(backend.connect()?);
+    let snapshot = client.snapshot()?;
+
+    // Reuse the same connection as the persistent client — no extra handshake.
+    Ok((snapshot, SharedTradfriClient::from(client)))
+}
+
+/// Pairing response from `15011/9063`.
+#[derive(Debug, Deserialize)]
+struct PairingResponse {
+    /// Pre-shared key for the new identity
+    #[serde(rename = "9091")]
+    psk: String,
+}
+
+/// Register `identity` with the gateway using the security code printed on
+/// its back, and return the pre-shared key generated for it.
+///
+/// Each identity can only be registered once; the gateway rejects repeats.
+/// The gateway can't list or revoke identities (only a factory reset clears
+/// them), so callers record the ones they register; `check_identity` tells
+/// whether one still works.
+pub fn pair(host: &str, security_code: &str, identity: &str) -> Result<String> {
+    let mut coap = DtlsCoap::new(host, PAIRING_IDENTITY, security_code, RetryPolicy::default())
+        .context("Failed to connect with security code (is it correct?)")?;
+    let payload = serde_json::json!({ "9090": identity });
+    let response = coap
+        .post("15011/9063", payload.to_string().as_bytes())
+        .context("Gateway rejected the pairing request")?;
+    let parsed: PairingResponse =
+        serde_json::from_slice(&response).context("Failed to parse pairing response")?;
+    Ok(parsed.psk)
+}
+
+/// Whether the gateway at `host` accepts `identity` with `psk`: a handshake
+/// and one request. A rejected key fails the handshake.
+pub fn check_identity(host: &str, identity: &str, psk: &str) -> Result<()> {
+    let mut coap = DtlsCoap::new(host, identity, psk, RetryPolicy::default())?;
+    coap.get(GATEWAY_INFO_PATH).map(drop)
+}
+
+/// The thread started by `spawn_observer`. Dropping this stops it: the
+/// thread closes its session within a second instead of holding it open
+/// until the next notification, so a replaced subscription doesn't leave
+/// an extra session on the gateway.
+pub struct Observer {
+    stop: Arc<AtomicBool>,
+}
+
+impl Drop for Observer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Observe every device in `ids` on a dedicated session and forward state
+/// changes to `tx` as they arrive, until the returned `Observer` is dropped.
+///
+/// Notifications arrive unsolicited, so they get their own session rather than
+/// sharing the request/response stream of `SharedTradfriClient`. The thread
+/// reconnects with backoff and sends `ObserveEvent::Ended` after
+/// `OBSERVE_MAX_FAILURES` consecutive failed sessions. It also exits quietly
+/// once the receiver is dropped.
+pub fn spawn_observer(backend: Backend, ids: Vec<u64>, tx: mpsc::Sender<ObserveEvent>) -> Observer {
+    let stop = Arc::new(AtomicBool::new(false));
+    let observer = Observer { stop: Arc::clone(&stop) };
+    std::thread::spawn(move || {
+        let mut failures = 0;
+        loop {
+            let started = Instant::now();
+            let err = match observe_session(&backend, &ids, &stop, &tx) {
+                Ok(()) => return,
+                Err(e) => e,
+            };
+            // A session that stayed up for a while counts as a fresh start.
+            if started.elapsed() >= OBSERVE_RESUBSCRIBE {
+                failures = 0;
+            }
+            failures += 1;
+            tracing::warn!("observe session failed ({}/{}): {}", failures, OBSERVE_MAX_FAILURES, err);
+            if failures >= OBSERVE_MAX_FAILURES {
+                let _ = tx.send(ObserveEvent::Ended(err.to_string()));
+                return;
+            }
+            let retry_at = Instant::now() + Duration::from_secs(1 << failures);
+            while Instant::now() < retry_at {
+                if stop.load(Ordering::Relaxed) {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(100));
+            }
+        }
+    });
+    observer
+}
+
+/// Run one observe session until it fails (`Err`), or `stop` is set or the
+/// receiver is gone (`Ok`).
+fn observe_session(
+    backend: &Backend,
+    ids: &[u64],
+    stop: &AtomicBool,
+    tx: &mpsc::Sender<ObserveEvent>,
+) -> Result<()> {
+    let mut transport = backend.connect().context("Failed to connect observe session")?;
+    let paths: Vec<String> = ids.iter().map(|id| format!("15001/{}", id)).collect();
+    transport.observe(&paths, stop, &mut |path, payload| forward_notification(path, payload, tx))
+}
+
+/// Parse a device notification and forward it. Returns `false` once the receiver is gone.
+fn forward_notification(path: &str, payload: &[u8], tx: &mpsc::Sender<ObserveEvent>) -> bool {
+    let device = match serde_json::from_slice::<TradfriDevice>(payload) {
+        Ok(device) => device,
+        Err(e) => {
+            tracing::warn!("observe of {}: JSON parse failed: {}", path, e);
+            return true;
+        }
+    };
+    match device.into_event() {
+        Some(event) => tx.send(event).is_ok(),
+        None => true,
+    }
+}
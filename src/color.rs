@@ -0,0 +1,98 @@
+//! Colour conversions for Trådfri colour (CWS) bulbs.
+//!
+//! The gateway works in CIE 1931 xy and hue/saturation scaled to integer
+//! ranges; the TUI wants sRGB. Conversions use the wide-gamut D65 matrix
+//! that Hue and Trådfri bulbs are built around.
+
+/// Raw scale of `5709`/`5710` (x/y): 0..=65535 maps to 0.0..=1.0.
+pub const XY_SCALE: f32 = 65535.0;
+/// Raw scale of `5707` (hue): 0..=65535 maps to 0..360°.
+pub const HUE_SCALE: f32 = 65535.0;
+/// Raw scale of `5708` (saturation): 0..=65279 maps to 0.0..=1.0.
+pub const SATURATION_SCALE: f32 = 65279.0;
+
+/// Colour temperature in Kelvin → mireds (`5711`).
+pub fn kelvin_to_mireds(kelvin: u16) -> u16 {
+    (1_000_000 / u32::from(kelvin.max(1))) as u16
+}
+
+/// Mireds → Kelvin, for display.
+pub fn mireds_to_kelvin(mireds: u16) -> u16 {
+    (1_000_000 / u32::from(mireds.max(1))) as u16
+}
+
+/// sRGB (0-255) → CIE xy.
+pub fn rgb_to_xy(r: u8, g: u8, b: u8) -> (f32, f32) {
+    let [r, g, b] = [r, g, b].map(|c| srgb_to_linear(c as f32 / 255.0));
+    let x = r * 0.649_926 + g * 0.103_455 + b * 0.197_109;
+    let y = r * 0.234_327 + g * 0.743_075 + b * 0.022_598;
+    let z = g * 0.053_077 + b * 1.035_763;
+    let sum = x + y + z;
+    if sum <= f32::EPSILON {
+        // Black has no chromaticity; use the D65 white point.
+        return (0.3127, 0.3290);
+    }
+    (x / sum, y / sum)
+}
+
+/// CIE xy → sRGB (0-255) at full brightness, for display.
+pub fn xy_to_rgb(x: f32, y: f32) -> (u8, u8, u8) {
+    let y = y.max(0.0001);
+    let big_y = 1.0;
+    let big_x = big_y / y * x;
+    let big_z = big_y / y * (1.0 - x - y);
+
+    let r = big_x * 1.656_492 - big_y * 0.354_851 - big_z * 0.255_038;
+    let g = -big_x * 0.707_196 + big_y * 1.655_397 + big_z * 0.036_152;
+    let b = big_x * 0.051_713 - big_y * 0.121_364 + big_z * 1.011_53;
+
+    // Normalise so the brightest channel is 1.0, then gamma-encode.
+    let max = r.max(g).max(b).max(f32::EPSILON);
+    let [r, g, b] = [r, g, b].map(|c| (linear_to_srgb((c / max).max(0.0)) * 255.0).round() as u8);
+    (r, g, b)
+}
+
+/// Scale xy (0-1) to the gateway's integer range.
+pub fn xy_to_raw(x: f32, y: f32) -> (u16, u16) {
+    let scale = |v: f32| (v.clamp(0.0, 1.0) * XY_SCALE).round() as u16;
+    (scale(x), scale(y))
+}
+
+/// Gateway integer xy → 0-1.
+pub fn raw_to_xy(x: u16, y: u16) -> (f32, f32) {
+    (x as f32 / XY_SCALE, y as f32 / XY_SCALE)
+}
+
+/// Hue (degrees), saturation and value (0-1) → sRGB (0-255).
+pub fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> (u8, u8, u8) {
+    let h = hue.rem_euclid(360.0) / 60.0;
+    let c = value * saturation.clamp(0.0, 1.0);
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = value - c;
+    let to_byte = |v: f32| ((v + m) * 255.0).round() as u8;
+    (to_byte(r), to_byte(g), to_byte(b))
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c > 0.04045 {
+        ((c + 0.055) / 1.055).powf(2.4)
+    } else {
+        c / 12.92
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
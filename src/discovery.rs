@@ -0,0 +1,497 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// The gateway's CoAPS port, used unless `gateway.host` names another.
+pub const COAP_PORT: u16 = 5684;
+const MDNS_ADDR: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_PORT: u16 = 5353;
+const SERVICE: &str = "_coap._udp.local";
+/// Trådfri gateways announce themselves as `gw-<mac>._coap._udp.local`.
+const GATEWAY_PREFIX: &str = "gw-";
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+/// The query is re-sent this often until an answer arrives.
+const QUERY_INTERVAL: Duration = Duration::from_secs(1);
+const CACHE_FILENAME: &str = "gateway";
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_AAAA: u16 = 28;
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+/// "QU" bit: ask responders to answer by unicast.
+const CLASS_UNICAST_RESPONSE: u16 = 0x8000;
+
+/// True when `host` asks for auto-discovery (`""` or `"auto"`).
+pub fn is_auto(host: &str) -> bool {
+    let host = host.trim();
+    host.is_empty() || host.eq_ignore_ascii_case("auto")
+}
+
+/// Split an explicit `gateway.host` into host and port: `192.168.0.131`,
+/// `gw-b8d7af2b.local:5684`, `fe80::1`, `[fe80::1%eth0]:5684`. Only checks
+/// the syntax; names are resolved when connecting.
+pub fn parse_host(host: &str) -> Result<(String, u16)> {
+    let host = host.trim();
+    let (name, port) = if let Some(rest) = host.strip_prefix('[') {
+        let (name, rest) = rest.split_once(']').context("Missing ']' after IPv6 address")?;
+        if !is_ipv6(name) {
+            anyhow::bail!("'{}' is not an IPv6 address", name);
+        }
+        match rest {
+            "" => (name, None),
+            _ => (name, Some(rest.strip_prefix(':').context("Expected ':PORT' after ']'")?)),
+        }
+    } else {
+        match host.split_once(':') {
+            // More than one colon: a bare IPv6 address, which can't carry a port.
+            Some(_) if host.matches(':').count() > 1 => {
+                if !is_ipv6(host) {
+                    anyhow::bail!("'{}' is not an IPv6 address; put the address in brackets to add a port", host);
+                }
+                (host, None)
+            }
+            Some((name, port)) => (name, Some(port)),
+            None => (host, None),
+        }
+    };
+
+    let valid_name = is_ipv6(name)
+        || (!name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_')));
+    if !valid_name {
+        anyhow::bail!("'{}' is not a host name or IP address", name);
+    }
+    let port = match port {
+        Some(port) => port
+            .parse::<u16>()
+            .ok()
+            .filter(|p| *p != 0)
+            .with_context(|| format!("Invalid port '{}'", port))?,
+        None => COAP_PORT,
+    };
+    Ok((name.to_string(), port))
+}
+
+/// An IPv6 address, optionally with a `%zone` (needed for link-local ones).
+fn is_ipv6(addr: &str) -> bool {
+    let addr = addr.split_once('%').map_or(addr, |(addr, _)| addr);
+    addr.parse::<Ipv6Addr>().is_ok()
+}
+
+/// Turn the configured `gateway.host` into an address to connect to. Runs on
+/// every (re)connect, so a gateway whose DNS name moved is followed.
+///
+/// Names go through the system resolver, with `.local` names asked over
+/// multicast DNS when it doesn't know them. For auto hosts the last
+/// discovered address is reused from the cache; only when there is none does
+/// this browse mDNS (and cache the result).
+pub fn resolve_host(host: &str) -> Result<SocketAddr> {
+    if !is_auto(host) {
+        let (name, port) = parse_host(host)?;
+        return resolve_name(&name, port);
+    }
+    if let Some(cached) = read_cache() {
+        return Ok(SocketAddr::new(cached, COAP_PORT));
+    }
+    let addr = discover_gateway()?;
+    tracing::info!("discovered gateway at {}", addr);
+    write_cache(addr);
+    Ok(SocketAddr::new(addr, COAP_PORT))
+}
+
+fn resolve_name(name: &str, port: u16) -> Result<SocketAddr> {
+    let resolved = (name, port).to_socket_addrs().map(|addrs| {
+        // Prefer IPv4, as for discovered gateways.
+        let mut addrs: Vec<SocketAddr> = addrs.collect();
+        addrs.sort_by_key(|a| a.is_ipv6());
+        addrs.first().copied()
+    });
+    match resolved {
+        Ok(Some(addr)) => Ok(addr),
+        _ if name.to_ascii_lowercase().ends_with(".local") => {
+            let addr = resolve_mdns(name)?;
+            Ok(SocketAddr::new(addr, port))
+        }
+        Ok(None) => anyhow::bail!("No address found for '{}'", name),
+        Err(e) => Err(e).with_context(|| format!("Failed to resolve '{}'", name)),
+    }
+}
+
+/// Ask for the A record of a `.local` name over multicast DNS.
+fn resolve_mdns(name: &str) -> Result<IpAddr> {
+    let name = name.trim_end_matches('.');
+    mdns_query(name, TYPE_A, |records, _| {
+        records.iter().find_map(|r| match r {
+            Record::Addr { name: owner, addr } if owner.eq_ignore_ascii_case(name) => Some(*addr),
+            _ => None,
+        })
+    })
+    .with_context(|| format!("'{}' not found via mDNS within {}s", name, DISCOVERY_TIMEOUT.as_secs()))
+}
+
+/// Drop the cached address, e.g. after the gateway stopped answering there.
+/// The next `resolve_host` browses mDNS again.
+pub fn forget_cached() {
+    let _ = fs::remove_file(cache_path());
+}
+
+/// Browse `_coap._udp.local` and return the address of the first Trådfri gateway.
+pub fn discover_gateway() -> Result<IpAddr> {
+    mdns_query(SERVICE, TYPE_PTR, find_gateway).with_context(|| {
+        format!(
+            "No Trådfri gateway found via mDNS within {}s; set gateway.host explicitly",
+            DISCOVERY_TIMEOUT.as_secs()
+        )
+    })
+}
+
+/// Send an mDNS question for `name` until `pick` finds an address in a
+/// response (given its records and sender), or `DISCOVERY_TIMEOUT` passes.
+fn mdns_query(name: &str, rtype: u16, pick: impl Fn(&[Record], IpAddr) -> Option<IpAddr>) -> Result<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").context("Failed to bind mDNS socket")?;
+    socket.set_multicast_ttl_v4(255)?;
+    socket.set_read_timeout(Some(Duration::from_millis(250)))?;
+    let target = SocketAddr::from((MDNS_ADDR, MDNS_PORT));
+    let query = build_query(name, rtype);
+
+    let started = Instant::now();
+    let mut last_query: Option<Instant> = None;
+    let mut buf = [0u8; 9000];
+    while started.elapsed() < DISCOVERY_TIMEOUT {
+        if last_query.is_none_or(|t| t.elapsed() >= QUERY_INTERVAL) {
+            socket
+                .send_to(&query, target)
+                .context("Failed to send mDNS query")?;
+            last_query = Some(Instant::now());
+        }
+        let Ok((len, from)) = socket.recv_from(&mut buf) else {
+            continue;
+        };
+        match parse_response(&buf[..len]) {
+            Ok(records) => {
+                if let Some(addr) = pick(&records, from.ip()) {
+                    return Ok(addr);
+                }
+            }
+            Err(e) => tracing::debug!("ignoring mDNS packet from {}: {}", from, e),
+        }
+    }
+    anyhow::bail!("no answer")
+}
+
+fn cache_path() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("frostlux")
+        .join(CACHE_FILENAME)
+}
+
+fn read_cache() -> Option<IpAddr> {
+    fs::read_to_string(cache_path()).ok()?.trim().parse().ok()
+}
+
+fn write_cache(addr: IpAddr) {
+    let path = cache_path();
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    if let Err(e) = fs::write(&path, addr.to_string()) {
+        tracing::warn!("failed to cache gateway address: {}", e);
+    }
+}
+
+// ── DNS wire format ─────────────────────────────────────
+
+#[derive(Debug)]
+enum Record {
+    Ptr { name: String, target: String },
+    Srv { name: String, target: String },
+    Addr { name: String, addr: IpAddr },
+}
+
+fn build_query(name: &str, rtype: u16) -> Vec<u8> {
+    // Header: id 0, flags 0, one question, no records.
+    let mut packet = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+    for label in name.split('.') {
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend_from_slice(&rtype.to_be_bytes());
+    packet.extend_from_slice(&(CLASS_IN | CLASS_UNICAST_RESPONSE).to_be_bytes());
+    packet
+}
+
+fn parse_response(packet: &[u8]) -> Result<Vec<Record>> {
+    let header = packet.get(..12).context("Truncated DNS header")?;
+    let count = |i: usize| u16::from_be_bytes([header[i], header[i + 1]]) as usize;
+    let questions = count(4);
+    let records = count(6) + count(8) + count(10);
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        let (_, next) = read_name(packet, pos)?;
+        pos = next + 4;
+    }
+
+    let mut out = Vec::new();
+    for _ in 0..records {
+        let (name, next) = read_name(packet, pos)?;
+        let fixed = packet.get(next..next + 10).context("Truncated DNS record")?;
+        let rtype = u16::from_be_bytes([fixed[0], fixed[1]]);
+        let rdlen = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+        let rdata_start = next + 10;
+        let rdata = packet
+            .get(rdata_start..rdata_start + rdlen)
+            .context("Truncated DNS record data")?;
+        pos = rdata_start + rdlen;
+
+        match rtype {
+            TYPE_PTR => {
+                let (target, _) = read_name(packet, rdata_start)?;
+                out.push(Record::Ptr { name, target });
+            }
+            TYPE_SRV if rdlen > 6 => {
+                let (target, _) = read_name(packet, rdata_start + 6)?;
+                out.push(Record::Srv { name, target });
+            }
+            TYPE_A if rdlen == 4 => {
+                let addr = Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3]);
+                out.push(Record::Addr { name, addr: addr.into() });
+            }
+            TYPE_AAAA if rdlen == 16 => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(rdata);
+                out.push(Record::Addr { name, addr: Ipv6Addr::from(octets).into() });
+            }
+            _ => {}
+        }
+    }
+    Ok(out)
+}
+
+/// Read a (possibly compressed) name at `pos`; returns it and the offset after it.
+fn read_name(packet: &[u8], mut pos: usize) -> Result<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut end = None;
+    // Bound pointer chasing so a malicious packet can't loop forever.
+    for _ in 0..128 {
+        let len = *packet.get(pos).context("Truncated DNS name")? as usize;
+        if len == 0 {
+            return Ok((labels.join("."), end.unwrap_or(pos + 1)));
+        }
+        if len & 0xC0 == 0xC0 {
+            let low = *packet.get(pos + 1).context("Truncated DNS pointer")? as usize;
+            end.get_or_insert(pos + 2);
+            pos = ((len & 0x3F) << 8) | low;
+            continue;
+        }
+        let label = packet
+            .get(pos + 1..pos + 1 + len)
+            .context("Truncated DNS label")?;
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        pos += 1 + len;
+    }
+    anyhow::bail!("DNS name compression loop")
+}
+
+/// Pick the gateway address out of one response. Falls back to the sender's
+/// address when the responder left out the A/AAAA record.
+fn find_gateway(records: &[Record], from: IpAddr) -> Option<IpAddr> {
+    let instance = records.iter().find_map(|r| match r {
+        Record::Ptr { name, target }
+            if name.eq_ignore_ascii_case(SERVICE)
+                && target.to_ascii_lowercase().starts_with(GATEWAY_PREFIX) =>
+        {
+            Some(target.as_str())
+        }
+        _ => None,
+    })?;
+
+    let hosts: HashMap<&str, &str> = records
+        .iter()
+        .filter_map(|r| match r {
+            Record::Srv { name, target } => Some((name.as_str(), target.as_str())),
+            _ => None,
+        })
+        .collect();
+    let host = hosts.get(instance).copied();
+
+    // Prefer IPv4: it's what the gateway's DTLS service is reliably bound to.
+    let mut addrs: Vec<IpAddr> = records
+        .iter()
+        .filter_map(|r| match r {
+            Record::Addr { name, addr } if Some(name.as_str()) == host => Some(*addr),
+            _ => None,
+        })
+        .collect();
+    addrs.sort_by_key(|a| a.is_ipv6());
+    Some(addrs.first().copied().unwrap_or(from))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_host_accepts_names_addresses_and_ports() {
+        let cases = [
+            ("192.168.0.10:5684", "192.168.0.10", 5684),
+            ("192.168.0.10", "192.168.0.10", COAP_PORT),
+            (" gw-b8d7af2b.local:5685 ", "gw-b8d7af2b.local", 5685),
+            ("[fe80::1]:5684", "fe80::1", 5684),
+            ("[fe80::1%eth0]", "fe80::1%eth0", COAP_PORT),
+            ("fe80::1", "fe80::1", COAP_PORT),
+            // Without brackets the last group is part of the address.
+            ("fe80::1:5685", "fe80::1:5685", COAP_PORT),
+        ];
+        for (input, name, port) in cases {
+            assert_eq!(parse_host(input).unwrap(), (name.to_string(), port), "{}", input);
+        }
+    }
+
+    #[test]
+    fn parse_host_rejects_bad_hosts_and_ports() {
+        let cases = [
+            "gw.local:abc",
+            "host:",
+            "host:0",
+            "host:65536",
+            "",
+            ":5684",
+            "gw local",
+            "[fe80::1",
+            "[fe80::1]5684",
+            "[gw.local]:5684",
+            "fe80::zz",
+        ];
+        for input in cases {
+            assert!(parse_host(input).is_err(), "{:?} was accepted", input);
+        }
+    }
+
+    /// A gateway's answer to the `_coap._udp.local` PTR query, as captured.
+    /// Every name after the first is compressed; offsets in `<>`.
+    fn gateway_reply() -> Vec<u8> {
+        [
+            // Header: response, one answer, three additional records
+            &b"\x00\x00\x84\x00\x00\x00\x00\x01\x00\x00\x00\x03"[..],
+            // 12: PTR _coap._udp.local -> gw-b8d7af2b3c4d.<12>
+            b"\x05_coap\x04_udp\x05local\x00",
+            b"\x00\x0c\x00\x01\x00\x00\x11\x94\x00\x12",
+            b"\x0fgw-b8d7af2b3c4d\xc0\x0c",
+            // 58: SRV <40> -> port 5684, gw-b8d7af2b3c4d.<23>
+            b"\xc0\x28\x00\x21\x80\x01\x00\x00\x00\x78\x00\x18",
+            b"\x00\x00\x00\x00\x16\x34\x0fgw-b8d7af2b3c4d\xc0\x17",
+            // 94: A <76> 192.168.0.131
+            b"\xc0\x4c\x00\x01\x80\x01\x00\x00\x00\x78\x00\x04\xc0\xa8\x00\x83",
+            // 110: AAAA <76> fe80::1
+            b"\xc0\x4c\x00\x1c\x80\x01\x00\x00\x00\x78\x00\x10\xfe\x80\0\0\0\0\0\0\0\0\0\0\0\0\0\x01",
+        ]
+        .concat()
+    }
+
+    /// A header announcing one answer, followed by `body`.
+    fn one_answer(body: &[u8]) -> Vec<u8> {
+        [&b"\x00\x00\x84\x00\x00\x00\x00\x01\x00\x00\x00\x00"[..], body].concat()
+    }
+
+    const FROM: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1));
+
+    #[test]
+    fn captured_reply_decompresses_to_the_gateway() {
+        let records = parse_response(&gateway_reply()).unwrap();
+        let names: Vec<String> = records
+            .iter()
+            .map(|r| match r {
+                Record::Ptr { name, target } => format!("PTR {} {}", name, target),
+                Record::Srv { name, target } => format!("SRV {} {}", name, target),
+                Record::Addr { name, addr } => format!("ADDR {} {}", name, addr),
+            })
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "PTR _coap._udp.local gw-b8d7af2b3c4d._coap._udp.local",
+                "SRV gw-b8d7af2b3c4d._coap._udp.local gw-b8d7af2b3c4d.local",
+                "ADDR gw-b8d7af2b3c4d.local 192.168.0.131",
+                "ADDR gw-b8d7af2b3c4d.local fe80::1",
+            ]
+        );
+        assert_eq!(find_gateway(&records, FROM), Some(IpAddr::from([192, 168, 0, 131])));
+    }
+
+    #[test]
+    fn replies_without_addresses_fall_back_to_the_sender() {
+        // Only the answer and the SRV record.
+        let reply = gateway_reply();
+        let mut reply = reply[..94].to_vec();
+        reply[11] = 1;
+        let records = parse_response(&reply).unwrap();
+        assert_eq!(find_gateway(&records, FROM), Some(FROM));
+
+        // Another CoAP device answering the same browse isn't a gateway.
+        let other = one_answer(
+            b"\x05_coap\x04_udp\x05local\x00\x00\x0c\x00\x01\x00\x00\x00\x78\x00\x0a\x08shelly-1\xc0\x0c",
+        );
+        assert_eq!(find_gateway(&parse_response(&other).unwrap(), FROM), None);
+    }
+
+    #[test]
+    fn every_truncation_of_a_reply_is_an_error() {
+        let reply = gateway_reply();
+        for len in 0..reply.len() {
+            assert!(parse_response(&reply[..len]).is_err(), "cut at {} parsed", len);
+        }
+    }
+
+    #[test]
+    fn malformed_names_are_errors() {
+        let cases: [(&str, &[u8]); 6] = [
+            ("pointer to itself", b"\xc0\x0c"),
+            ("pointers to each other", b"\xc0\x0e\xc0\x0c"),
+            ("pointer past the end", b"\xc0\xff"),
+            ("pointer cut short", b"\xc0"),
+            ("label past the end", b"\x3fgw"),
+            ("no terminating zero", b"\x05local"),
+        ];
+        for (what, body) in cases {
+            assert!(parse_response(&one_answer(body)).is_err(), "{}", what);
+        }
+        // A long chain of pointers ends rather than being followed forever.
+        let mut chain = Vec::new();
+        for i in 0..200u16 {
+            chain.extend_from_slice(&(0xc000 | (12 + 2 * (i + 1))).to_be_bytes());
+        }
+        chain.push(0);
+        assert!(parse_response(&one_answer(&chain)).is_err());
+    }
+
+    #[test]
+    fn malformed_records_are_errors() {
+        let owner = b"\x05local\x00";
+        let record = |fixed_and_data: &[u8]| one_answer(&[&owner[..], fixed_and_data].concat());
+        let cases: [(&str, Vec<u8>); 4] = [
+            ("header cut short", b"\x00\x00\x84\x00\x00".to_vec()),
+            ("counts with nothing behind them", b"\x00\x00\x84\x00\xff\xff\xff\xff\xff\xff\xff\xff".to_vec()),
+            ("data longer than the packet", record(b"\x00\x01\x00\x01\x00\x00\x00\x78\xff\xff\xc0\xa8")),
+            ("PTR target outside the packet", record(b"\x00\x0c\x00\x01\x00\x00\x00\x78\x00\x02\xc3\x00")),
+        ];
+        for (what, packet) in cases {
+            assert!(parse_response(&packet).is_err(), "{}", what);
+        }
+        // Records of unknown types, or too short for their type, are skipped.
+        let odd = [
+            &b"\x00\x00\x84\x00\x00\x00\x00\x02\x00\x00\x00\x00"[..],
+            owner,
+            b"\x00\x10\x00\x01\x00\x00\x00\x78\x00\x03\x02hi",
+            owner,
+            b"\x00\x01\x00\x01\x00\x00\x00\x78\x00\x02\xc0\xa8",
+        ]
+        .concat();
+        assert!(parse_response(&odd).unwrap().is_empty());
+    }
+}
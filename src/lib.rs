@@ -0,0 +1,7 @@
+//! The gateway protocol layer: CoAP over DTLS-PSK and the in-memory gateway.
+//! Split out of the binary so integration tests can talk to `frostlux sim`
+//! the same way the app does.
+
+pub mod coap;
+pub mod color;
+pub mod discovery;
@@ -1,8 +1,12 @@
 mod app;
-mod coap;
+mod backup;
+mod cli;
+mod sim;
 mod tradfri;
 mod ui;
 
+use frostlux::{coap, color, discovery};
+
 use anyhow::{Context, Result};
 use crossterm::{
     event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind},
@@ -16,6 +20,7 @@ use std::io;
 use std::time::{Duration, Instant};
 
 use app::{load_config, App, Scene};
+use coap::{Backend, MemoryGateway};
 use ui::{alacritty_marker_theme_is_light, frost_theme_from_config};
 
 /// How often to check the Alacritty theme marker file (fast path).
@@ -27,9 +32,22 @@ fn main() -> Result<()> {
     // Parse CLI args
     let args: Vec<String> = std::env::args().collect();
 
+    // Subcommands
+    match args.get(1).map(String::as_str) {
+        Some("pair") => return cli::run_pair(&args[2..]),
+        Some("blind") => return cli::run_blind(&args[2..]),
+        Some("sim") => return sim::run_sim(&args[2..]),
+        Some("gateway") => return cli::run_gateway(&args[2..]),
+        Some("rename") => return cli::run_rename(&args[2..]),
+        Some("backup") => return cli::run_backup(&args[2..]),
+        Some("restore") => return cli::run_restore(&args[2..]),
+        _ => {}
+    }
+
     // Check for --scene / -s flag (headless mode)
     if let Some(scene_arg) = parse_scene_arg(&args) {
-        return run_headless_scene(&scene_arg);
+        let room = cli::flag_value(&args, "--room");
+        return run_headless_scene(&scene_arg, room.as_deref(), parse_fade_arg(&args)?);
     }
 
     // Check for --help
@@ -44,11 +62,19 @@ fn main() -> Result<()> {
     // Load config
     let config = load_config().context("Failed to load config")?;
 
+    // --demo runs against an in-memory gateway instead of real hardware
+    let demo = args.iter().any(|a| a == "--demo");
+    let gateway = if demo {
+        Backend::Memory(MemoryGateway::demo())
+    } else {
+        config.gateway.backend()
+    };
+
     // Validate credentials
-    if config.gateway.identity.is_empty() || config.gateway.psk.is_empty() {
+    if !demo && (config.gateway.identity.is_empty() || config.gateway.psk.is_empty()) {
         eprintln!("Error: Gateway credentials not configured.");
         eprintln!("Edit ~/.config/frostlux/config.toml with your identity and psk.");
-        eprintln!("\nTo pair with your gateway, use the security code on the back of it.");
+        eprintln!("\nTo pair with your gateway, run: frostlux pair --code SECURITY_CODE");
         std::process::exit(1);
     }
 
@@ -59,8 +85,8 @@ fn main() -> Result<()> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    // Create app — spawns a background thread for DTLS connect + parallel fetch
-    let mut app = App::new(config);
+    // Create app — spawns a background thread for DTLS connect + pipelined fetch
+    let mut app = App::new(config, gateway);
 
     let result = run_app(&mut terminal, &mut app);
 
@@ -95,8 +121,8 @@ fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, app: &mut App)
         // Poll for completed background refresh
         app.poll_refresh();
 
-        // Start background refresh when interval has elapsed
-        if app.last_refresh.elapsed() >= refresh_interval {
+        // Fall back to polling when the gateway isn't pushing updates
+        if !app.is_observing && app.last_refresh.elapsed() >= refresh_interval {
             app.start_background_refresh();
         }
 
@@ -148,6 +174,70 @@ fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, app: &mut App)
                     continue;
                 }
 
+                // Inline rename captures typing
+                if app.rename.is_some() {
+                    match key.code {
+                        KeyCode::Enter => app.confirm_rename(),
+                        KeyCode::Esc => app.rename = None,
+                        KeyCode::Backspace => {
+                            if let Some(edit) = &mut app.rename {
+                                edit.input.pop();
+                            }
+                        }
+                        KeyCode::Char(c) => {
+                            if let Some(edit) = &mut app.rename {
+                                edit.input.push(c);
+                            }
+                        }
+                        _ => {}
+                    }
+                    continue;
+                }
+
+                // Yes/no question (config update after rename, gateway reboot,
+                // forgetting identities)
+                if app.confirm.is_some() {
+                    match key.code {
+                        KeyCode::Char('j' | 'J' | 'y' | 'Y') => app.answer_confirm(true),
+                        KeyCode::Char('n' | 'N') | KeyCode::Esc => app.answer_confirm(false),
+                        _ => {}
+                    }
+                    continue;
+                }
+
+                // Gateway popup blocks other input
+                if app.show_gateway {
+                    match key.code {
+                        KeyCode::Char('G') | KeyCode::Esc | KeyCode::Enter => app.show_gateway = false,
+                        KeyCode::Char('R') => app.start_gateway_fetch(),
+                        KeyCode::Char('B') => app.request_reboot(),
+                        KeyCode::Char('D') => app.request_forget_identities(),
+                        _ => {}
+                    }
+                    continue;
+                }
+
+                // Scene picker blocks other input
+                if app.scene_picker.is_some() {
+                    match key.code {
+                        KeyCode::Char('j') | KeyCode::Down => app.scene_picker_move(1),
+                        KeyCode::Char('k') | KeyCode::Up => app.scene_picker_move(-1),
+                        KeyCode::Enter => {
+                            if let Err(e) = app.confirm_scene_picker() {
+                                app.set_status(&format!("Error: {}", e));
+                            }
+                        }
+                        KeyCode::Char('f') => {
+                            if let Err(e) = app.fade_scene_picker() {
+                                app.set_status(&format!("Error: {}", e));
+                            }
+                        }
+                        KeyCode::Char('s') | KeyCode::Esc => app.scene_picker = None,
+                        _ => {}
+                    }
+                    continue;
+                }
+
                 match key.code {
                     // Quit
                     KeyCode::Char('q') | KeyCode::Esc => app.should_quit = true,
@@ -155,6 +245,7 @@ fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, app: &mut App)
                     // Navigation
                     KeyCode::Char('j') | KeyCode::Down => app.select_next(),
                     KeyCode::Char('k') | KeyCode::Up => app.select_prev(),
+                    KeyCode::Tab => app.toggle_view(),
 
                     // Toggle
                     KeyCode::Char(' ') => {
@@ -185,6 +276,14 @@ fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, app: &mut App)
                         let _ = app.cycle_color_temp(false);
                     }
 
+                    // Colour (colour bulbs)
+                    KeyCode::Char(']') => {
+                        let _ = app.cycle_color(true);
+                    }
+                    KeyCode::Char('[') => {
+                        let _ = app.cycle_color(false);
+                    }
+
                     // Scenes
                     KeyCode::Char('a') => {
                         let _ = app.apply_scene(Scene::AllOn);
@@ -213,8 +312,14 @@ fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, app: &mut App)
                     KeyCode::Char('g') => {
                         let _ = app.apply_scene(Scene::GoodMorning);
                     }
-
-                    // Force refresh (non-blocking)
+                    KeyCode::Char('s') => app.open_scene_picker(),
+                    KeyCode::Char('x') => app.cancel_fade(),
+                    KeyCode::Char('i') => app.show_details = !app.show_details,
+                    KeyCode::Char('G') => app.open_gateway_info(),
+                    KeyCode::Char('N') => app.start_rename(),
+
+                    // Force refresh (non-blocking); while offline, reconnect now
+                    KeyCode::Char('R') if app.client.is_none() => app.retry_connect(),
                     KeyCode::Char('R') => {
                         app.start_background_refresh();
                         app.set_status("Refreshing...");
@@ -249,26 +354,56 @@ fn parse_scene_arg(args: &[String]) -> Option<String> {
     None
 }
 
-fn run_headless_scene(scene_name: &str) -> Result<()> {
+/// `--fade` → `Some(None)` (configured length), `--fade MINUTES` → `Some(Some(length))`.
+/// Anything after `--fade` that isn't another flag must be a usable number of minutes.
+fn parse_fade_arg(args: &[String]) -> Result<Option<Option<Duration>>> {
+    if !args.iter().any(|a| a == "--fade" || a.starts_with("--fade=")) {
+        return Ok(None);
+    }
+    let Some(value) = cli::flag_value(args, "--fade").filter(|v| !v.starts_with("--")) else {
+        return Ok(Some(None));
+    };
+    let length = value
+        .parse::<f32>()
+        .ok()
+        .filter(|minutes| *minutes >= 0.0)
+        .and_then(|minutes| Duration::try_from_secs_f32(minutes * 60.0).ok())
+        .with_context(|| format!("Invalid --fade '{}': expected a number of minutes, e.g. --fade 20", value))?;
+    Ok(Some(Some(length)))
+}
+
+fn run_headless_scene(
+    scene_name: &str,
+    room: Option<&str>,
+    fade: Option<Option<Duration>>,
+) -> Result<()> {
     let config = load_config().context("Failed to load config")?;
 
     if config.gateway.identity.is_empty() || config.gateway.psk.is_empty() {
         anyhow::bail!("Gateway credentials not configured in ~/.config/frostlux/config.toml");
     }
 
-    let scene = Scene::from_str(scene_name).with_context(|| {
-        format!(
-            "Unknown scene: '{}'\n\nAvailable scenes: {}",
-            scene_name,
-            Scene::all()
-                .iter()
-                .map(|s| s.name().to_lowercase().replace(' ', "-"))
-                .collect::<Vec<_>>()
-                .join(", ")
-        )
-    })?;
-
-    App::run_scene_headless(&config, scene)
+    // Built-in scenes take precedence; anything else is looked up among the
+    // gateway's own moods.
+    let Some(scene) = Scene::from_str(scene_name) else {
+        if fade.is_some() {
+            anyhow::bail!("--fade only works with built-in scenes, not gateway moods");
+        }
+        return App::run_mood_headless(&config, scene_name, room).with_context(|| {
+            format!(
+                "Unknown scene: '{}'\n\nAvailable scenes: {} (or a gateway mood name)",
+                scene_name,
+                Scene::all()
+                    .iter()
+                    .map(|s| s.name().to_lowercase().replace(' ', "-"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        });
+    };
+
+    let fade = fade.map(|length| length.unwrap_or_else(|| config.scenes.fade_duration()));
+    App::run_scene_headless(&config, scene, room, fade)
 }
 
 fn print_help() {
@@ -278,21 +413,53 @@ fn print_help() {
 USAGE:
     frostlux              Launch interactive TUI
     frostlux --scene NAME Apply a scene directly (no TUI)
+    frostlux --scene NAME --room ROOM
+                          Apply a scene to one room (gateway group)
+    frostlux --scene NAME --fade [MINUTES]
+                          Fade slowly to a scene (default: scenes.fade_minutes);
+                          lights you change meanwhile are left alone
+    frostlux pair --code CODE [--host HOST] [--identity NAME]
+                          Pair with a gateway and save credentials
+    frostlux blind        List blinds with position and battery
+    frostlux blind NAME open|close|stop|POSITION
+                          Move a blind (POSITION: 0 = open, 100 = closed)
+    frostlux rename [--device | --room] OLD NEW
+                          Rename a device or room on the gateway
+    frostlux gateway      Show gateway firmware, clock, NTP server and warnings
+    frostlux gateway reboot [--yes]
+                          Restart the gateway (asks first unless --yes)
+    frostlux gateway identities
+                          List identities paired from here and check them
+    frostlux gateway forget NAME... [--yes]
+                          Drop identities from that list (asks first)
+    frostlux backup > FILE
+                          Save devices, rooms, moods and smart tasks as JSON
+    frostlux restore FILE [--dry-run] [--yes]
+                          Re-apply names, rooms and moods from a backup
+    frostlux --demo       Launch the TUI against a simulated home (no gateway)
+    frostlux sim [--port N] [--latency MS] [--loss PERCENT] [--home FILE]
+                          Run a simulated gateway (DTLS on localhost) for testing
     frostlux --help       Show this help
 
 SCENES:
     on, off, movie, bright, cozy, night, evening, reading, morning
+    Any other name is looked up among the gateway's moods (IKEA app scenes).
 
 EXAMPLES:
     frostlux --scene movie     Apply movie scene
     frostlux -s off            Turn all lights off
     frostlux -s cozy           Apply cozy scene
+    frostlux -s night --room Sovrummet
+    frostlux -s night --fade 20    Fade to night over 20 minutes
+    frostlux -s Relax --room Vardagsrum   Activate a gateway mood
+    frostlux pair --host 192.168.0.131 --code ABCD1234efgh5678
+    frostlux blind Vardagsrum 50    Lower a blind halfway
 
 CONFIG:
     ~/.config/frostlux/config.toml
 
     [gateway]
-    host = "192.168.0.131"
+    host = "auto"              # or an IP, IPv6 or host name, optionally :PORT
     identity = "tradfri_xxx"
     psk = "your_psk"
 
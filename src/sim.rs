@@ -0,0 +1,484 @@
+use anyhow::{Context, Result};
+use coap_lite::block_handler::BlockValue;
+use coap_lite::{CoapOption, MessageClass, MessageType, Packet, RequestType, ResponseType};
+use openssl::ssl::{ErrorCode, HandshakeError, Ssl, SslContext, SslMethod, SslStream};
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::AtomicBool;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::cli::flag_value;
+use crate::coap::{MemoryGateway, Transport};
+
+const DEFAULT_BIND: &str = "127.0.0.1";
+const DEFAULT_PORT: u16 = 5684;
+const DEFAULT_IDENTITY: &str = "frostlux-sim";
+const DEFAULT_PSK: &str = "frostlux-sim-psk";
+const DEFAULT_CODE: &str = "SIMULATOR";
+/// DTLS identity the real gateway pairs with (together with its security code).
+const PAIRING_IDENTITY: &str = "Client_identity";
+const BUF_SIZE: usize = 4096;
+/// Largest payload in one message; bigger ones are served block-wise (Block2).
+const BLOCK_SIZE: usize = 1024;
+/// Responses remembered per session, so retransmitted requests get the same
+/// answer instead of being applied twice.
+const RESPONSE_CACHE: usize = 32;
+/// How often an idle session checks for notifications to send.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// ── Gateway simulator ───────────────────────────────────
+
+/// Known DTLS identities and their pre-shared keys.
+type Identities = Arc<Mutex<HashMap<String, String>>>;
+
+/// Network conditions applied to CoAP traffic once a session is up.
+#[derive(Debug, Clone, Copy)]
+struct Network {
+    /// Delay before each response
+    latency: Duration,
+    /// Share of datagrams dropped in each direction, 0-1
+    loss: f32,
+}
+
+impl Network {
+    fn drops(&self) -> bool {
+        let mut bytes = [0u8; 2];
+        self.loss > 0.0
+            && openssl::rand::rand_bytes(&mut bytes).is_ok()
+            && (u16::from_be_bytes(bytes) as f32 / 65536.0) < self.loss
+    }
+}
+
+/// One peer's datagrams, handed over by the dispatcher, as a stream for openssl.
+struct PeerChannel {
+    socket: Arc<UdpSocket>,
+    peer: SocketAddr,
+    rx: mpsc::Receiver<Vec<u8>>,
+    /// `None` during the handshake, which is never degraded.
+    network: Option<Network>,
+}
+
+impl Read for PeerChannel {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.rx.recv_timeout(POLL_INTERVAL) {
+            Ok(datagram) if !self.network.is_some_and(|n| n.drops()) => {
+                let len = datagram.len().min(buf.len());
+                buf[..len].copy_from_slice(&datagram[..len]);
+                Ok(len)
+            }
+            Ok(_) | Err(mpsc::RecvTimeoutError::Timeout) => {
+                Err(std::io::ErrorKind::WouldBlock.into())
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                Err(std::io::ErrorKind::ConnectionAborted.into())
+            }
+        }
+    }
+}
+
+impl Write for PeerChannel {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.network.is_some_and(|n| n.drops()) {
+            return Ok(buf.len());
+        }
+        self.socket.send_to(buf, self.peer)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A home loaded with `--home FILE`: raw gateway JSON, as the gateway
+/// returns it from `15001/<id>`, `15004/<id>`, `15005/<group>/<id>` and
+/// `15010/<id>`. A `frostlux backup` file is one.
+#[derive(Debug, Deserialize)]
+struct Home {
+    #[serde(default)]
+    devices: Vec<serde_json::Value>,
+    #[serde(default)]
+    groups: Vec<serde_json::Value>,
+    /// Moods by group ID
+    #[serde(default)]
+    moods: HashMap<u64, Vec<serde_json::Value>>,
+    #[serde(default)]
+    smart_tasks: Vec<serde_json::Value>,
+    /// Overrides for the gateway's own `15011/15012` document
+    #[serde(default)]
+    gateway: Option<serde_json::Value>,
+}
+
+fn load_home(path: &str) -> Result<MemoryGateway> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+    let home: Home = serde_json::from_str(&content).with_context(|| format!("Failed to parse {}", path))?;
+    let gateway = MemoryGateway::default();
+    for device in home.devices {
+        gateway.add_device(device);
+    }
+    for group in home.groups {
+        gateway.add_group(group);
+    }
+    for (group_id, moods) in home.moods {
+        for mood in moods {
+            gateway.add_mood(group_id, mood);
+        }
+    }
+    for task in home.smart_tasks {
+        gateway.add_smart_task(task);
+    }
+    if let Some(info) = home.gateway {
+        gateway.set_gateway_info(info);
+    }
+    Ok(gateway)
+}
+
+/// `frostlux sim [--port N] [--bind ADDR] [--latency MS] [--loss PERCENT]
+/// [--home FILE] [--identity NAME --psk KEY] [--code CODE]`
+///
+/// Serves CoAP over DTLS-PSK like a Trådfri gateway, backed by the in-memory
+/// gateway (the `--demo` home unless `--home` is given). PUTs change its
+/// state and are pushed to observers, so the whole DTLS path can be exercised
+/// without hardware.
+pub fn run_sim(args: &[String]) -> Result<()> {
+    let bind = flag_value(args, "--bind").unwrap_or_else(|| DEFAULT_BIND.to_string());
+    let port: u16 = match flag_value(args, "--port") {
+        Some(port) => port.parse().with_context(|| format!("Invalid port '{}'", port))?,
+        None => DEFAULT_PORT,
+    };
+    let latency = match flag_value(args, "--latency") {
+        Some(ms) => Duration::from_millis(ms.parse().with_context(|| format!("Invalid latency '{}'", ms))?),
+        None => Duration::ZERO,
+    };
+    let loss = match flag_value(args, "--loss") {
+        Some(pct) => {
+            let pct: f32 = pct
+                .trim_end_matches('%')
+                .parse()
+                .ok()
+                .filter(|p| (0.0..=100.0).contains(p))
+                .with_context(|| format!("Invalid loss '{}': use 0-100", pct))?;
+            pct / 100.0
+        }
+        None => 0.0,
+    };
+    let network = Network { latency, loss };
+    let identity = flag_value(args, "--identity").unwrap_or_else(|| DEFAULT_IDENTITY.to_string());
+    let psk = flag_value(args, "--psk").unwrap_or_else(|| DEFAULT_PSK.to_string());
+    let code = flag_value(args, "--code").unwrap_or_else(|| DEFAULT_CODE.to_string());
+    let gateway = match flag_value(args, "--home") {
+        Some(path) => load_home(&path)?,
+        None => MemoryGateway::demo(),
+    };
+
+    let identities: Identities = Arc::new(Mutex::new(HashMap::from([
+        (identity.clone(), psk.clone()),
+        (PAIRING_IDENTITY.to_string(), code.clone()),
+    ])));
+    let ctx = server_context(identities.clone())?;
+    let socket = Arc::new(
+        UdpSocket::bind((bind.as_str(), port)).with_context(|| format!("Failed to bind {}:{}", bind, port))?,
+    );
+
+    // With `--port 0` the OS picks the port; print the one it picked.
+    let addr = socket.local_addr().context("Failed to read the bound address")?;
+    println!("FrostLux sim: gateway listening on {}", addr);
+    println!("  identity = \"{}\"", identity);
+    println!("  psk      = \"{}\"", psk);
+    println!("  pairing code {}", code);
+    println!("  latency {} ms, loss {}%", latency.as_millis(), loss * 100.0);
+
+    // Route datagrams to one session thread per peer address.
+    let mut peers: HashMap<SocketAddr, mpsc::Sender<Vec<u8>>> = HashMap::new();
+    let mut buf = [0u8; BUF_SIZE];
+    loop {
+        let (len, peer) = socket.recv_from(&mut buf).context("Failed to receive")?;
+        let datagram = buf[..len].to_vec();
+        if let Some(tx) = peers.get(&peer) {
+            if tx.send(datagram.clone()).is_ok() {
+                continue;
+            }
+            // That session has ended; this is a new handshake.
+            peers.remove(&peer);
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let _ = tx.send(datagram);
+        peers.insert(peer, tx);
+
+        let channel = PeerChannel { socket: Arc::clone(&socket), peer, rx, network: None };
+        let ssl = Ssl::new(&ctx).context("Failed to create SSL instance")?;
+        let gateway = gateway.clone();
+        let identities = identities.clone();
+        std::thread::spawn(move || {
+            match Session::accept(ssl, channel, gateway, network, identities) {
+                Ok(session) => {
+                    println!("{}: connected", peer);
+                    if let Err(e) = session.run() {
+                        println!("{}: session ended: {}", peer, e);
+                    }
+                }
+                Err(e) => println!("{}: handshake failed: {}", peer, e),
+            }
+        });
+    }
+}
+
+fn server_context(identities: Identities) -> Result<SslContext> {
+    let mut ctx = SslContext::builder(SslMethod::dtls()).context("Failed to create DTLS context")?;
+    ctx.set_cipher_list("PSK-AES128-CCM8:@SECLEVEL=0")
+        .context("Failed to set cipher")?;
+    ctx.set_psk_server_callback(move |_ssl, identity, psk_buf| {
+        let identity = String::from_utf8_lossy(identity.unwrap_or_default()).into_owned();
+        let identities = identities.lock().unwrap_or_else(|e| e.into_inner());
+        // An unknown identity gets no key, which fails the handshake.
+        let Some(psk) = identities.get(&identity) else { return Ok(0) };
+        let len = psk.len().min(psk_buf.len());
+        psk_buf[..len].copy_from_slice(&psk.as_bytes()[..len]);
+        Ok(len)
+    });
+    Ok(ctx.build())
+}
+
+/// One client's DTLS session.
+struct Session {
+    stream: SslStream<PeerChannel>,
+    gateway: MemoryGateway,
+    network: Network,
+    identities: Identities,
+    /// Recent (message ID, response) pairs
+    responses: VecDeque<(u16, Vec<u8>)>,
+    /// Observed paths: token and last sequence number
+    observed: HashMap<String, (Vec<u8>, u32)>,
+    notify_tx: mpsc::Sender<(String, Vec<u8>)>,
+    notify_rx: mpsc::Receiver<(String, Vec<u8>)>,
+    msg_id: u16,
+}
+
+impl Session {
+    fn accept(
+        ssl: Ssl,
+        channel: PeerChannel,
+        gateway: MemoryGateway,
+        network: Network,
+        identities: Identities,
+    ) -> Result<Self> {
+        let started = Instant::now();
+        let mut handshake = ssl.accept(channel);
+        let mut stream = loop {
+            match handshake {
+                Ok(stream) => break stream,
+                Err(HandshakeError::WouldBlock(mid)) if started.elapsed() < HANDSHAKE_TIMEOUT => {
+                    handshake = mid.handshake();
+                }
+                Err(HandshakeError::WouldBlock(_)) => anyhow::bail!("timed out"),
+                Err(HandshakeError::Failure(mid)) => anyhow::bail!("{}", mid.error()),
+                Err(HandshakeError::SetupFailure(e)) => return Err(e.into()),
+            }
+        };
+        stream.get_mut().network = Some(network);
+
+        let (notify_tx, notify_rx) = mpsc::channel();
+        let mut seed = [0u8; 2];
+        let _ = openssl::rand::rand_bytes(&mut seed);
+        Ok(Self {
+            stream,
+            gateway,
+            network,
+            identities,
+            responses: VecDeque::new(),
+            observed: HashMap::new(),
+            notify_tx,
+            notify_rx,
+            msg_id: u16::from_be_bytes(seed),
+        })
+    }
+
+    fn run(mut self) -> Result<()> {
+        let mut buf = [0u8; BUF_SIZE];
+        loop {
+            match self.stream.ssl_read(&mut buf) {
+                Ok(len) => {
+                    let Ok(request) = Packet::from_bytes(&buf[..len]) else { continue };
+                    if let Some(response) = self.handle(request) {
+                        std::thread::sleep(self.network.latency);
+                        self.stream.ssl_write(&response)?;
+                    }
+                }
+                Err(e) if e.code() == ErrorCode::WANT_READ => {}
+                Err(e) if e.code() == ErrorCode::ZERO_RETURN => return Ok(()),
+                Err(e) => return Err(e.into()),
+            }
+
+            while let Ok((path, payload)) = self.notify_rx.try_recv() {
+                if let Some(notification) = self.notification(&path, payload) {
+                    self.stream.ssl_write(&notification)?;
+                }
+            }
+        }
+    }
+
+    fn next_msg_id(&mut self) -> u16 {
+        self.msg_id = self.msg_id.wrapping_add(1);
+        self.msg_id
+    }
+
+    /// Answer one request, or `None` for ACKs, resets and pings.
+    fn handle(&mut self, request: Packet) -> Option<Vec<u8>> {
+        let kind = request.header.get_type();
+        if matches!(kind, MessageType::Acknowledgement | MessageType::Reset) {
+            return None;
+        }
+        let MessageClass::Request(method) = request.header.code else {
+            // CoAP ping (empty CON): answer with a reset.
+            let mut pong = Packet::new();
+            pong.header.set_type(MessageType::Reset);
+            pong.header.message_id = request.header.message_id;
+            return pong.to_bytes().ok();
+        };
+        if kind == MessageType::Confirmable {
+            if let Some((_, cached)) = self.responses.iter().find(|(id, _)| *id == request.header.message_id) {
+                return Some(cached.clone());
+            }
+        }
+
+        let path = request
+            .get_option(CoapOption::UriPath)
+            .map(|segments| {
+                segments
+                    .iter()
+                    .map(|s| String::from_utf8_lossy(s).into_owned())
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+            .unwrap_or_default();
+
+        let mut response = Packet::new();
+        if kind == MessageType::Confirmable {
+            response.header.set_type(MessageType::Acknowledgement);
+            response.header.message_id = request.header.message_id;
+        } else {
+            response.header.set_type(MessageType::NonConfirmable);
+            response.header.message_id = self.next_msg_id();
+        }
+        response.set_token(request.get_token().to_vec());
+
+        let (code, payload) = match method {
+            RequestType::Get => match self.gateway.get(&path) {
+                Ok(payload) => {
+                    if request.get_observe_value().is_some_and(|v| v.ok() == Some(0)) {
+                        response.set_observe_value(self.observe(&path, request.get_token()));
+                    }
+                    (ResponseType::Content, payload)
+                }
+                Err(_) => (ResponseType::NotFound, Vec::new()),
+            },
+            RequestType::Put => match self.gateway.put(&path, &request.payload) {
+                Ok(()) => (ResponseType::Changed, Vec::new()),
+                Err(_) if self.gateway.get(&path).is_ok() => (ResponseType::BadRequest, Vec::new()),
+                Err(_) => (ResponseType::NotFound, Vec::new()),
+            },
+            RequestType::Post => match self.gateway.post(&path, &request.payload) {
+                Ok(payload) => {
+                    self.register_identity(&path, &request.payload, &payload);
+                    (ResponseType::Created, payload)
+                }
+                Err(_) => (ResponseType::MethodNotAllowed, Vec::new()),
+            },
+            _ => (ResponseType::MethodNotAllowed, Vec::new()),
+        };
+        response.header.code = MessageClass::Response(code);
+        response.payload = block(&request, &mut response, payload);
+
+        let bytes = response.to_bytes().ok()?;
+        if kind == MessageType::Confirmable {
+            self.responses.push_back((request.header.message_id, bytes.clone()));
+            if self.responses.len() > RESPONSE_CACHE {
+                self.responses.pop_front();
+            }
+        }
+        Some(bytes)
+    }
+
+    /// Start (or refresh) an observation and return its sequence number.
+    fn observe(&mut self, path: &str, token: &[u8]) -> u32 {
+        if let Some((existing, seq)) = self.observed.get_mut(path) {
+            *existing = token.to_vec();
+            return *seq;
+        }
+        self.observed.insert(path.to_string(), (token.to_vec(), 1));
+
+        let mut gateway = self.gateway.clone();
+        let tx = self.notify_tx.clone();
+        let paths = vec![path.to_string()];
+        std::thread::spawn(move || {
+            // The first callback is the current state, already in the response.
+            let mut initial = true;
+            // Ends with the session: the next notification finds it gone.
+            let never = AtomicBool::new(false);
+            let _ = gateway.observe(&paths, &never, &mut |path, payload| {
+                if std::mem::take(&mut initial) {
+                    return true;
+                }
+                tx.send((path.to_string(), payload.to_vec())).is_ok()
+            });
+        });
+        1
+    }
+
+    fn notification(&mut self, path: &str, payload: Vec<u8>) -> Option<Vec<u8>> {
+        let msg_id = self.next_msg_id();
+        let (token, seq) = self.observed.get_mut(path)?;
+        *seq += 1;
+        let mut packet = Packet::new();
+        packet.header.set_type(MessageType::NonConfirmable);
+        packet.header.message_id = msg_id;
+        packet.header.code = MessageClass::Response(ResponseType::Content);
+        packet.set_token(token.clone());
+        packet.set_observe_value(*seq);
+        packet.payload = payload;
+        packet.to_bytes().ok()
+    }
+
+    /// After a successful pairing, accept the new identity with its key.
+    fn register_identity(&self, path: &str, request: &[u8], response: &[u8]) {
+        if path != "15011/9063" {
+            return;
+        }
+        let identity = serde_json::from_slice::<serde_json::Value>(request)
+            .ok()
+            .and_then(|v| v["9090"].as_str().map(str::to_string));
+        let psk = serde_json::from_slice::<serde_json::Value>(response)
+            .ok()
+            .and_then(|v| v["9091"].as_str().map(str::to_string));
+        if let (Some(identity), Some(psk)) = (identity, psk) {
+            println!("paired identity '{}'", identity);
+            self.identities
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(identity, psk);
+        }
+    }
+}
+
+/// The slice of `payload` the request asked for, adding a Block2 option to
+/// `response` when it doesn't fit in one message.
+fn block(request: &Packet, response: &mut Packet, payload: Vec<u8>) -> Vec<u8> {
+    let requested = request
+        .get_first_option_as::<BlockValue>(CoapOption::Block2)
+        .and_then(|b| b.ok());
+    if requested.is_none() && payload.len() <= BLOCK_SIZE {
+        return payload;
+    }
+    let (num, size) = requested.map_or((0, BLOCK_SIZE), |b| (b.num as usize, b.size().min(BLOCK_SIZE)));
+    let start = (num * size).min(payload.len());
+    let end = (start + size).min(payload.len());
+    let more = end < payload.len();
+    if let Ok(value) = BlockValue::new(num, more, size) {
+        response.add_option_as(CoapOption::Block2, value);
+    }
+    payload[start..end].to_vec()
+}
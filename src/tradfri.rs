@@ -1,5 +1,11 @@
-use crate::coap::{LightInfo, SharedTradfriClient};
+use crate::coap::{
+    self, BlindInfo, DeviceInfo, GatewayInfo, GroupInfo, LightInfo, MoodInfo, SharedTradfriClient,
+};
+
+pub use crate::coap::{DeviceKind, PowerSource};
+use crate::color;
 use anyhow::Result;
+use std::time::Duration;
 
 /// Trådfri standard color temperature hex values (cold → neutral → warm).
 pub const COLOR_TEMP_COLD: &str = "f5faf6";
@@ -10,6 +16,36 @@ pub const COLOR_TEMP_WARM: &str = "efd275";
 pub const COLOR_TEMPS: [&str; 3] = [COLOR_TEMP_COLD, COLOR_TEMP_NEUTRAL, COLOR_TEMP_WARM];
 pub const COLOR_TEMP_LABELS: [&str; 3] = ["cold", "neutral", "warm"];
 
+/// Kelvin equivalents of the cold and neutral presets, used by scenes.
+pub const KELVIN_COLD: u16 = 4000;
+pub const KELVIN_NEUTRAL: u16 = 2700;
+
+/// Mireds range of white spectrum bulbs (4000K–2200K).
+pub const WHITE_SPECTRUM_MIREDS: (u16, u16) = (250, 454);
+/// Colour bulbs reach further in both directions (6500K–2000K).
+pub const COLOR_MIREDS: (u16, u16) = (153, 500);
+/// `+` / `-` step: about a dozen steps across the white spectrum range.
+pub const MIREDS_STEP: u16 = 17;
+
+/// Gateway clock drift (seconds) beyond which gateway-side schedules
+/// (smart tasks, wake-up timers) fire at the wrong time.
+pub const CLOCK_DRIFT_WARNING: u64 = 120;
+/// The newest gateway firmware this build knows about; IKEA may have shipped
+/// a later one since.
+pub const KNOWN_GATEWAY_FIRMWARE: [u32; 3] = [1, 21, 31];
+
+/// Named colours that `[` / `]` cycle through on colour bulbs.
+pub const COLOR_PALETTE: [(&str, (u8, u8, u8)); 8] = [
+    ("red", (255, 0, 0)),
+    ("orange", (255, 127, 0)),
+    ("yellow", (255, 220, 0)),
+    ("green", (0, 255, 0)),
+    ("cyan", (0, 255, 255)),
+    ("blue", (0, 0, 255)),
+    ("purple", (160, 32, 240)),
+    ("pink", (255, 105, 180)),
+];
+
 /// Light representation for the TUI
 #[derive(Debug, Clone)]
 pub struct Light {
@@ -20,7 +56,49 @@ pub struct Light {
     pub brightness: u8,
     /// Color hex string (e.g. "f1e0b5" for warm)
     pub color_hex: Option<String>,
+    /// Hue, 0-65535 (colour bulbs only)
+    pub hue: Option<u16>,
+    /// Saturation, 0-65279 (colour bulbs only)
+    pub saturation: Option<u16>,
+    /// CIE xy, each 0-65535
+    pub color_xy: Option<(u16, u16)>,
+    /// Colour temperature in mireds (white spectrum bulbs)
+    pub mireds: Option<u16>,
     pub reachable: bool,
+    /// Control outlet (smart plug): on/off only
+    pub plug: bool,
+}
+
+/// A light's colour, typed by what the bulb can do.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightColor<'a> {
+    /// No colour information (plain white bulb)
+    None,
+    /// White spectrum bulb showing one of the gateway's hex presets
+    White(&'a str),
+    /// Colour bulb: CIE xy (0-1), hue (degrees) and saturation (0-1)
+    Color {
+        xy: Option<(f32, f32)>,
+        hue: Option<f32>,
+        saturation: Option<f32>,
+    },
+}
+
+impl LightColor<'_> {
+    /// Approximate sRGB, e.g. for drawing a swatch.
+    pub fn rgb(&self) -> Option<(u8, u8, u8)> {
+        match *self {
+            LightColor::Color { xy: Some((x, y)), .. } => Some(color::xy_to_rgb(x, y)),
+            LightColor::Color { hue: Some(h), saturation: Some(s), .. } => {
+                Some(color::hsv_to_rgb(h, s, 1.0))
+            }
+            LightColor::White(hex) if hex.len() == 6 => {
+                let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+                Some((channel(0)?, channel(2)?, channel(4)?))
+            }
+            _ => None,
+        }
+    }
 }
 
 impl From<LightInfo> for Light {
@@ -31,7 +109,12 @@ impl From<LightInfo> for Light {
             on: info.on,
             brightness: info.brightness,
             color_hex: info.color_hex,
+            hue: info.hue,
+            saturation: info.saturation,
+            color_xy: info.color_xy,
+            mireds: info.mireds,
             reachable: info.reachable,
+            plug: info.plug,
         }
     }
 }
@@ -42,8 +125,74 @@ impl Light {
         ((self.brightness as f32 / 254.0) * 100.0).round() as u8
     }
 
-    /// Color temperature label based on hex.
+    /// Colour bulbs report hue; white spectrum bulbs don't.
+    pub fn is_color(&self) -> bool {
+        self.hue.is_some()
+    }
+
+    /// What the device can do, for the detail pane.
+    pub fn capabilities(&self) -> Vec<&'static str> {
+        if self.plug {
+            return vec!["on/off"];
+        }
+        let mut caps = vec!["on/off", "dimmable"];
+        if self.mireds.is_some() {
+            caps.push("white spectrum");
+        }
+        if self.is_color() {
+            caps.push("colour");
+        }
+        caps
+    }
+
+    pub fn color(&self) -> LightColor<'_> {
+        if self.is_color() {
+            LightColor::Color {
+                xy: self.color_xy.map(|(x, y)| color::raw_to_xy(x, y)),
+                hue: self.hue.map(|h| h as f32 / color::HUE_SCALE * 360.0),
+                saturation: self.saturation.map(|s| s as f32 / color::SATURATION_SCALE),
+            }
+        } else if let Some(hex) = self.color_hex.as_deref() {
+            LightColor::White(hex)
+        } else {
+            LightColor::None
+        }
+    }
+
+    /// Index of the palette entry closest to the current colour, if any.
+    pub fn palette_index(&self) -> Option<usize> {
+        let (x, y) = color::raw_to_xy(self.color_xy?.0, self.color_xy?.1);
+        COLOR_PALETTE
+            .iter()
+            .enumerate()
+            .map(|(i, (_, (r, g, b)))| {
+                let (px, py) = color::rgb_to_xy(*r, *g, *b);
+                (i, (px - x).powi(2) + (py - y).powi(2))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+
+    /// Supported mireds (min, max), or `None` if the bulb has no colour temperature.
+    pub fn mireds_range(&self) -> Option<(u16, u16)> {
+        self.mireds?;
+        Some(if self.is_color() { COLOR_MIREDS } else { WHITE_SPECTRUM_MIREDS })
+    }
+
+    /// Colour temperature in Kelvin, when the bulb reports it.
+    pub fn kelvin(&self) -> Option<u16> {
+        self.mireds.map(color::mireds_to_kelvin)
+    }
+
+    /// Color temperature label based on mireds (or hex for older firmware).
     pub fn color_temp_label(&self) -> &str {
+        if let Some(mireds) = self.mireds {
+            return match mireds {
+                m if m < 300 => "cold",
+                m if m >= 400 => "warm",
+                _ => "neutral",
+            };
+        }
         match self.color_hex.as_deref() {
             Some(COLOR_TEMP_COLD) => "cold",
             Some(COLOR_TEMP_NEUTRAL) => "neutral",
@@ -56,26 +205,338 @@ impl Light {
     }
 }
 
-/// Fetch all lights from the gateway (serial, uses existing connection).
-pub fn fetch_lights(client: &SharedTradfriClient) -> Result<Vec<Light>> {
-    let infos = client.list_lights()?;
-    let mut lights: Vec<Light> = infos.into_iter().map(Light::from).collect();
-    lights.sort_by(|a, b| a.name.cmp(&b.name));
-    Ok(lights)
+/// Blind representation for the TUI
+#[derive(Debug, Clone)]
+pub struct Blind {
+    pub id: u64,
+    pub name: String,
+    /// 0 = fully open (up), 100 = fully closed (down)
+    pub position: u8,
+    pub battery: Option<u8>,
+    pub reachable: bool,
+}
+
+impl From<BlindInfo> for Blind {
+    fn from(info: BlindInfo) -> Self {
+        Self {
+            id: info.id,
+            name: info.name,
+            position: info.position,
+            battery: info.battery,
+            reachable: info.reachable,
+        }
+    }
+}
+
+impl Blind {
+    /// How far open, 0-100 (the inverse of the gateway's position).
+    pub fn open_percent(&self) -> u8 {
+        100 - self.position.min(100)
+    }
+}
+
+/// Any paired device (lights, plugs, blinds, remotes, sensors, repeaters)
+#[derive(Debug, Clone)]
+pub struct Device {
+    pub id: u64,
+    pub name: String,
+    pub kind: DeviceKind,
+    pub manufacturer: String,
+    pub model: String,
+    pub firmware: String,
+    pub power_source: PowerSource,
+    pub battery: Option<u8>,
+    pub reachable: bool,
+    /// Unix seconds
+    pub last_seen: Option<u64>,
+}
+
+impl From<DeviceInfo> for Device {
+    fn from(info: DeviceInfo) -> Self {
+        Self {
+            id: info.id,
+            name: info.name,
+            kind: info.kind,
+            manufacturer: info.manufacturer,
+            model: info.model,
+            firmware: info.firmware,
+            power_source: info.power_source,
+            battery: info.battery,
+            reachable: info.reachable,
+            last_seen: info.last_seen,
+        }
+    }
+}
+
+impl Device {
+    /// "IKEA of Sweden TRADFRI remote control", or whichever part is known.
+    pub fn product(&self) -> String {
+        format!("{} {}", self.manufacturer, self.model).trim().to_string()
+    }
+}
+
+/// Group (room) representation for the TUI
+#[derive(Debug, Clone)]
+pub struct Group {
+    pub id: u64,
+    pub name: String,
+    pub mood_id: Option<u64>,
+    /// Member device IDs; may include remotes and sensors
+    pub member_ids: Vec<u64>,
+}
+
+impl From<GroupInfo> for Group {
+    fn from(info: GroupInfo) -> Self {
+        Self {
+            id: info.id,
+            name: info.name,
+            mood_id: info.mood_id,
+            member_ids: info.member_ids,
+        }
+    }
+}
+
+/// Gateway mood (native scene) belonging to a group
+#[derive(Debug, Clone)]
+pub struct Mood {
+    pub id: u64,
+    pub group_id: u64,
+    pub name: String,
+}
+
+impl From<MoodInfo> for Mood {
+    fn from(info: MoodInfo) -> Self {
+        Self {
+            id: info.id,
+            group_id: info.group_id,
+            name: info.name,
+        }
+    }
 }
 
+/// The gateway's own health, for the gateway popup and `frostlux gateway`
+#[derive(Debug, Clone)]
+pub struct Gateway {
+    pub id: String,
+    pub firmware: String,
+    pub update_available: bool,
+    /// Unix seconds
+    pub time: Option<u64>,
+    pub ntp_server: String,
+    pub commissioning: bool,
+    /// Unix seconds
+    pub first_setup: Option<u64>,
+    /// Local clock when `time` was read, to measure drift against
+    pub fetched_at: u64,
+}
 
-/// Set a light on/off.
-pub fn set_power(client: &SharedTradfriClient, light_id: u64, on: bool) -> Result<()> {
-    client.set_power(light_id, on)
+impl From<GatewayInfo> for Gateway {
+    fn from(info: GatewayInfo) -> Self {
+        Self {
+            id: info.id,
+            firmware: info.firmware,
+            update_available: info.update_available,
+            time: info.time,
+            ntp_server: info.ntp_server,
+            commissioning: info.commissioning,
+            first_setup: info.first_setup,
+            fetched_at: unix_now(),
+        }
+    }
+}
+
+impl Gateway {
+    /// Gateway clock minus local clock, in seconds.
+    pub fn clock_drift(&self) -> Option<i64> {
+        Some(self.time? as i64 - self.fetched_at as i64)
+    }
+
+    /// "in sync", "5 min ahead", "2 h behind" or "not set".
+    pub fn clock_label(&self) -> String {
+        match self.clock_drift() {
+            Some(0) => "in sync".to_string(),
+            Some(drift) => format!(
+                "{} {}",
+                format_duration(drift.unsigned_abs()),
+                if drift > 0 { "ahead" } else { "behind" }
+            ),
+            None => "not set".to_string(),
+        }
+    }
+
+    /// Firmware older than `KNOWN_GATEWAY_FIRMWARE`. Unparseable versions
+    /// are given the benefit of the doubt.
+    pub fn firmware_outdated(&self) -> bool {
+        let version: Option<Vec<u32>> = self.firmware.split('.').map(|p| p.parse().ok()).collect();
+        version.is_some_and(|v| v.as_slice() < KNOWN_GATEWAY_FIRMWARE.as_slice())
+    }
+
+    /// Problems worth a warning: a wrong clock breaks gateway-side schedules.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        match self.clock_drift() {
+            Some(drift) if drift.unsigned_abs() > CLOCK_DRIFT_WARNING => {
+                warnings.push(format!("clock is {} (check NTP)", self.clock_label()));
+            }
+            None => warnings.push("clock not set".to_string()),
+            _ => {}
+        }
+        if self.ntp_server.is_empty() {
+            warnings.push("no NTP server".to_string());
+        }
+        if self.update_available {
+            warnings.push("firmware update available".to_string());
+        } else if self.firmware_outdated() {
+            warnings.push(format!(
+                "firmware {} is older than {}, the newest this build knows about",
+                self.firmware,
+                KNOWN_GATEWAY_FIRMWARE.map(|p| p.to_string()).join(".")
+            ));
+        }
+        warnings
+    }
+}
+
+/// "45 s", "12 min", "3 h", "2 d"
+pub fn format_duration(secs: u64) -> String {
+    match secs {
+        s if s < 60 => format!("{} s", s),
+        s if s < 3600 => format!("{} min", s / 60),
+        s if s < 86400 => format!("{} h", s / 3600),
+        s => format!("{} d", s / 86400),
+    }
+}
+
+pub fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Lights, groups and moods, sorted by name for display.
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    pub devices: Vec<Device>,
+    pub lights: Vec<Light>,
+    pub blinds: Vec<Blind>,
+    pub groups: Vec<Group>,
+    pub moods: Vec<Mood>,
+}
+
+impl From<coap::Snapshot> for Snapshot {
+    fn from(snapshot: coap::Snapshot) -> Self {
+        let mut devices: Vec<Device> = snapshot.devices.into_iter().map(Device::from).collect();
+        devices.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut lights: Vec<Light> = snapshot.lights.into_iter().map(Light::from).collect();
+        lights.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut blinds: Vec<Blind> = snapshot.blinds.into_iter().map(Blind::from).collect();
+        blinds.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut groups: Vec<Group> = snapshot.groups.into_iter().map(Group::from).collect();
+        groups.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut moods: Vec<Mood> = snapshot.moods.into_iter().map(Mood::from).collect();
+        moods.sort_by(|a, b| a.name.cmp(&b.name));
+        Self { devices, lights, blinds, groups, moods }
+    }
+}
+
+/// Fetch lights, groups and moods from the gateway (serial, uses existing connection).
+pub fn fetch_snapshot(client: &SharedTradfriClient) -> Result<Snapshot> {
+    client.snapshot().map(Snapshot::from)
+}
+
+pub fn fetch_gateway(client: &SharedTradfriClient) -> Result<Gateway> {
+    client.gateway_info().map(Gateway::from)
+}
+
+/// Set a light or plug on/off. Plugs switch instantly.
+pub fn set_power(
+    client: &SharedTradfriClient,
+    light: &Light,
+    on: bool,
+    transition: Option<Duration>,
+) -> Result<()> {
+    if light.plug {
+        client.set_plug_power(light.id, on)
+    } else {
+        client.set_power(light.id, on, transition)
+    }
 }
 
 /// Set brightness (0-254). Also turns the light on if brightness > 0.
-pub fn set_brightness(client: &SharedTradfriClient, light: &Light, brightness: u8) -> Result<()> {
-    client.set_brightness(light.id, brightness)
+pub fn set_brightness(
+    client: &SharedTradfriClient,
+    light: &Light,
+    brightness: u8,
+    transition: Option<Duration>,
+) -> Result<()> {
+    client.set_brightness(light.id, brightness, transition)
 }
 
 /// Set color temperature by hex value.
-pub fn set_color_temp(client: &SharedTradfriClient, light: &Light, hex: &str) -> Result<()> {
-    client.set_color(light.id, hex)
+pub fn set_color_temp(
+    client: &SharedTradfriClient,
+    light: &Light,
+    hex: &str,
+    transition: Option<Duration>,
+) -> Result<()> {
+    client.set_color(light.id, hex, transition)
+}
+
+/// Set colour temperature in Kelvin.
+pub fn set_kelvin(
+    client: &SharedTradfriClient,
+    light: &Light,
+    kelvin: u16,
+    transition: Option<Duration>,
+) -> Result<()> {
+    client.set_color_temp_kelvin(light.id, kelvin, transition)
+}
+
+/// Set an sRGB colour on a colour bulb.
+pub fn set_rgb(
+    client: &SharedTradfriClient,
+    light: &Light,
+    rgb: (u8, u8, u8),
+    transition: Option<Duration>,
+) -> Result<()> {
+    client.set_rgb(light.id, rgb, transition)
+}
+
+/// Set a whole group on/off.
+pub fn set_group_power(
+    client: &SharedTradfriClient,
+    group: &Group,
+    on: bool,
+    transition: Option<Duration>,
+) -> Result<()> {
+    client.set_group_power(group.id, on, transition)
+}
+
+/// Activate a gateway mood on its group.
+pub fn activate_mood(
+    client: &SharedTradfriClient,
+    mood: &Mood,
+    transition: Option<Duration>,
+) -> Result<()> {
+    client.set_group_mood(mood.group_id, mood.id, transition)
+}
+
+/// Set brightness (0-254) for a whole group.
+pub fn set_group_brightness(
+    client: &SharedTradfriClient,
+    group: &Group,
+    brightness: u8,
+    transition: Option<Duration>,
+) -> Result<()> {
+    client.set_group_brightness(group.id, brightness, transition)
+}
+
+/// Move a blind to `position` (0 = open, 100 = closed).
+pub fn set_blind_position(client: &SharedTradfriClient, blind: &Blind, position: u8) -> Result<()> {
+    client.set_blind_position(blind.id, position)
+}
+
+/// Stop a moving blind.
+pub fn stop_blind(client: &SharedTradfriClient, blind: &Blind) -> Result<()> {
+    client.stop_blind(blind.id)
 }
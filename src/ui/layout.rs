@@ -1,19 +1,24 @@
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
-    style::{Modifier, Style},
+    style::{Color, Modifier, Style},
     text::{Line, Span},
     widgets::{Block, Borders, Clear, List, ListItem, Paragraph},
     Frame,
 };
 
-use crate::app::App;
+use crate::app::{App, RenameTarget, SceneChoice, View};
+use crate::tradfri::{format_duration, unix_now};
 use crate::ui::theme::FrostTheme;
 
 const STATUS_POPUP_MAX_WIDTH: u16 = 50;
 const STATUS_POPUP_HEIGHT: u16 = 3;
 const HELP_POPUP_WIDTH: u16 = 50;
-const HELP_POPUP_HEIGHT: u16 = 18;
+const HELP_POPUP_HEIGHT: u16 = 27;
+const SCENE_PICKER_WIDTH: u16 = 44;
+const SCENE_PICKER_MAX_HEIGHT: u16 = 22;
 const BRIGHTNESS_BAR_SEGMENTS: usize = 10;
+const DETAIL_PANE_WIDTH: u16 = 38;
+const GATEWAY_POPUP_WIDTH: u16 = 56;
 
 // Compact layered snowflake: keeps the frosted look but fits tighter terminals.
 const SNOWFLAKE_OUTER: [&str; 5] = [
@@ -40,6 +45,25 @@ const SNOWFLAKE_CORE: [&str; 5] = [
     "      ✦      ",
 ];
 
+/// Name cell of a list row: the name, or the inline rename being typed.
+fn name_span(app: &App, target: RenameTarget, name: &str, is_selected: bool, theme: &FrostTheme) -> Span<'static> {
+    match &app.rename {
+        Some(edit) if edit.target == target => {
+            // Keep the end of long input, where the cursor is, in view.
+            let chars = edit.input.chars().count();
+            let tail: String = edit.input.chars().skip(chars.saturating_sub(24)).collect();
+            Span::styled(
+                format!("{:<25}", format!("{}▏", tail)),
+                theme.selected().add_modifier(Modifier::UNDERLINED),
+            )
+        }
+        _ => Span::styled(
+            truncate_name(name, 25),
+            if is_selected { theme.selected() } else { theme.normal() },
+        ),
+    }
+}
+
 /// Truncate `name` to `max_chars` display columns, appending "..." if needed.
 /// Pads with spaces to `max_chars` when the name is short — safe for multi-byte UTF-8.
 fn truncate_name(name: &str, max_chars: usize) -> String {
@@ -67,14 +91,43 @@ pub fn draw(frame: &mut Frame, app: &App, theme: &FrostTheme) {
         .split(area);
 
     draw_header(frame, chunks[0], app, theme);
-    draw_light_list(frame, chunks[1], app, theme);
+
+    // Detail pane to the right of the Lights / Blinds list
+    let mut list_area = chunks[1];
+    if app.show_details && matches!(app.view, View::Lights | View::Blinds) {
+        let split = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Min(40), Constraint::Length(DETAIL_PANE_WIDTH)])
+            .split(chunks[1]);
+        list_area = split[0];
+        draw_detail_pane(frame, split[1], app, theme);
+    }
+
+    match app.view {
+        View::Lights => draw_light_list(frame, list_area, app, theme),
+        View::Rooms => draw_room_list(frame, list_area, app, theme),
+        View::Blinds => draw_blind_list(frame, list_area, app, theme),
+        View::Batteries => draw_battery_list(frame, list_area, app, theme),
+    }
     draw_footer(frame, chunks[2], theme);
 
     // Status message overlay
-    if let Some(msg) = app.current_status() {
+    if let Some(question) = app.confirm_text() {
+        draw_status_popup(frame, area, &question, theme);
+    } else if let Some(msg) = app.current_status() {
         draw_status_popup(frame, area, msg, theme);
     }
 
+    // Scene picker overlay
+    if let Some(selected) = app.scene_picker {
+        draw_scene_picker(frame, area, app, selected, theme);
+    }
+
+    // Gateway info overlay
+    if app.show_gateway {
+        draw_gateway_popup(frame, area, app, theme);
+    }
+
     // Help overlay
     if app.show_help {
         draw_help_popup(frame, area, theme);
@@ -144,7 +197,7 @@ fn draw_header(frame: &mut Frame, area: Rect, app: &App, theme: &FrostTheme) {
     let off = app.lights_off();
     let total = app.lights.len();
 
-    let title_lines = vec![
+    let mut title_lines = vec![
         Line::from(""),
         Line::from(Span::styled(
             "FrostLux",
@@ -163,6 +216,45 @@ fn draw_header(frame: &mut Frame, area: Rect, app: &App, theme: &FrostTheme) {
             Span::styled("TOTAL", Style::default().fg(theme.dimmed)),
         ]),
     ];
+    let low_batteries = app.low_battery_devices().len();
+    if low_batteries > 0 {
+        title_lines[2].spans.extend([
+            Span::styled("  ·  ", Style::default().fg(theme.dimmed)),
+            Span::styled(format!("{} ", low_batteries), Style::default().fg(theme.bright_red)),
+            Span::styled("LOW BAT", Style::default().fg(theme.dimmed)),
+        ]);
+    }
+    if let (Some((error, _)), Some(retry_in)) = (&app.offline, app.offline_retry_in()) {
+        title_lines.push(Line::from(vec![
+            Span::styled("OFFLINE ", Style::default().fg(theme.bright_red)),
+            Span::styled(
+                format!("nytt försök om {} s", retry_in),
+                Style::default().fg(theme.foreground),
+            ),
+            Span::styled(format!("  ·  R nu  ·  {}", error), Style::default().fg(theme.dimmed)),
+        ]));
+    }
+    if let Some(warning) = app.gateway.as_ref().and_then(|g| g.warnings().into_iter().next()) {
+        title_lines.push(Line::from(vec![
+            Span::styled("GATEWAY ", Style::default().fg(theme.warm_yellow)),
+            Span::styled(warning, Style::default().fg(theme.foreground)),
+            Span::styled("  ·  G details", Style::default().fg(theme.dimmed)),
+        ]));
+    }
+    if let Some(fade) = &app.fade {
+        title_lines.push(Line::from(vec![
+            Span::styled("FADE ", Style::default().fg(theme.warm_yellow)),
+            Span::styled(fade.scene_name.clone(), Style::default().fg(theme.foreground)),
+            Span::styled(
+                format!(
+                    "  ·  {} min left  ·  {} lights  ·  x cancel",
+                    fade.remaining().as_secs().div_ceil(60),
+                    fade.active_lights()
+                ),
+                Style::default().fg(theme.dimmed),
+            ),
+        ]));
+    }
     let title = Paragraph::new(title_lines);
     frame.render_widget(title, header_chunks[1]);
 }
@@ -185,35 +277,50 @@ fn draw_light_list(frame: &mut Frame, area: Rect, app: &App, theme: &FrostTheme)
             };
 
             // Name (max 25 chars, safe for multi-byte UTF-8)
-            let name = truncate_name(&light.name, 25);
+            let name = name_span(app, RenameTarget::Device(light.id), &light.name, is_selected, theme);
 
             // Brightness bar
             let pct = light.brightness_percent() as usize;
             let filled = pct / BRIGHTNESS_BAR_SEGMENTS;
             let bar: String = "█".repeat(filled) + &"░".repeat(BRIGHTNESS_BAR_SEGMENTS - filled);
 
-            // Color temp indicator
+            // Color indicator: swatch for colour bulbs, temp for white spectrum
             let temp_label = light.color_temp_label();
-            let temp_indicator = match temp_label {
-                "warm" => "●",
-                "cold" => "○",
-                _ => " ",
-            };
-            let temp_color = if temp_label == "warm" {
-                theme.warm_yellow
+            let (temp_indicator, temp_color) = if light.is_color() {
+                let swatch = light.color().rgb().map_or(theme.foreground, |(r, g, b)| Color::Rgb(r, g, b));
+                ("■", swatch)
             } else {
-                theme.crystal_cyan
+                match temp_label {
+                    "warm" => ("●", theme.warm_yellow),
+                    "cold" => ("○", theme.crystal_cyan),
+                    _ => (" ", theme.crystal_cyan),
+                }
             };
 
+            if light.plug {
+                // Plugs are on/off only: no brightness bar or colour.
+                return ListItem::new(Line::from(vec![
+                    Span::styled(format!(" {} ", icon), Style::default().fg(icon_color)),
+                    name,
+                    Span::raw("  "),
+                    Span::styled(state_label, Style::default().fg(state_color)),
+                    Span::styled("⏻ plug", Style::default().fg(theme.dimmed)),
+                ]));
+            }
+
             let line = Line::from(vec![
                 Span::styled(format!(" {} ", icon), Style::default().fg(icon_color)),
-                Span::styled(name, if is_selected { theme.selected() } else { theme.normal() }),
+                name,
                 Span::raw("  "),
                 Span::styled(state_label, Style::default().fg(state_color)),
                 Span::styled(bar, Style::default().fg(theme.ice_blue)),
                 Span::styled(format!(" {:>3}%", pct), Style::default().fg(theme.foreground)),
                 Span::raw("  "),
                 Span::styled(temp_indicator, Style::default().fg(temp_color)),
+                Span::styled(
+                    light.kelvin().map(|k| format!(" {}K", k)).unwrap_or_default(),
+                    Style::default().fg(theme.dimmed),
+                ),
             ]);
 
             ListItem::new(line)
@@ -232,6 +339,307 @@ fn draw_light_list(frame: &mut Frame, area: Rect, app: &App, theme: &FrostTheme)
     frame.render_widget(list, area);
 }
 
+fn draw_room_list(frame: &mut Frame, area: Rect, app: &App, theme: &FrostTheme) {
+    let items: Vec<ListItem> = app
+        .groups
+        .iter()
+        .enumerate()
+        .map(|(i, group)| {
+            let is_selected = i == app.selected_group;
+            let members: Vec<_> = app.group_lights(group).collect();
+            let on = members.iter().filter(|l| l.on).count();
+
+            let (icon, icon_color) = if on > 0 {
+                ("*", theme.cold_green)
+            } else {
+                (".", theme.dimmed)
+            };
+
+            let name = name_span(app, RenameTarget::Group(group.id), &group.name, is_selected, theme);
+
+            // Brightness bar: brightest light that is on, like the gateway app.
+            let pct = members
+                .iter()
+                .filter(|l| l.on)
+                .map(|l| l.brightness_percent())
+                .max()
+                .unwrap_or(0) as usize;
+            let filled = pct / BRIGHTNESS_BAR_SEGMENTS;
+            let bar: String = "█".repeat(filled) + &"░".repeat(BRIGHTNESS_BAR_SEGMENTS - filled);
+
+            let line = Line::from(vec![
+                Span::styled(format!(" {} ", icon), Style::default().fg(icon_color)),
+                name,
+                Span::raw("  "),
+                Span::styled(
+                    format!("{:>2}/{:<2} ", on, members.len()),
+                    Style::default().fg(if on > 0 { theme.cold_green } else { theme.bright_red }),
+                ),
+                Span::styled(bar, Style::default().fg(theme.ice_blue)),
+                Span::styled(format!(" {:>3}%", pct), Style::default().fg(theme.foreground)),
+            ]);
+
+            ListItem::new(line)
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(theme.border())
+                .title(Span::styled(" Rooms ", theme.title())),
+        )
+        .style(theme.normal());
+
+    frame.render_widget(list, area);
+}
+
+fn draw_blind_list(frame: &mut Frame, area: Rect, app: &App, theme: &FrostTheme) {
+    let items: Vec<ListItem> = app
+        .blinds
+        .iter()
+        .enumerate()
+        .map(|(i, blind)| {
+            let is_selected = i == app.selected_blind;
+            let open = blind.open_percent() as usize;
+
+            let (icon, icon_color) = if !blind.reachable {
+                ("!", theme.bright_red)
+            } else if open > 0 {
+                ("▲", theme.cold_green)
+            } else {
+                ("▼", theme.dimmed)
+            };
+
+            let name = name_span(app, RenameTarget::Device(blind.id), &blind.name, is_selected, theme);
+
+            // Shade bar: filled part is the closed (lowered) share.
+            let closed = (100 - open) / BRIGHTNESS_BAR_SEGMENTS;
+            let bar: String = "▒".repeat(closed) + &" ".repeat(BRIGHTNESS_BAR_SEGMENTS - closed);
+
+            let (battery, battery_color) = match blind.battery {
+                Some(b) if b <= app.config.ui.battery_warning => (format!("bat {:>3}%", b), theme.bright_red),
+                Some(b) => (format!("bat {:>3}%", b), theme.dimmed),
+                None => (String::new(), theme.dimmed),
+            };
+
+            let line = Line::from(vec![
+                Span::styled(format!(" {} ", icon), Style::default().fg(icon_color)),
+                name,
+                Span::raw("  "),
+                Span::styled(format!("[{}]", bar), Style::default().fg(theme.ice_blue)),
+                Span::styled(format!(" {:>3}% open", open), Style::default().fg(theme.foreground)),
+                Span::raw("  "),
+                Span::styled(battery, Style::default().fg(battery_color)),
+            ]);
+
+            ListItem::new(line)
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(theme.border())
+                .title(Span::styled(" Blinds ", theme.title())),
+        )
+        .style(theme.normal());
+
+    frame.render_widget(list, area);
+}
+
+fn draw_battery_list(frame: &mut Frame, area: Rect, app: &App, theme: &FrostTheme) {
+    let threshold = app.config.ui.battery_warning;
+    let low = app.low_battery_devices();
+
+    let items: Vec<ListItem> = if low.is_empty() {
+        vec![ListItem::new(Line::from(Span::styled(
+            format!(" All batteries above {}%", threshold),
+            Style::default().fg(theme.cold_green),
+        )))]
+    } else {
+        low.iter()
+            .map(|device| {
+                let pct = device.battery.unwrap_or(0) as usize;
+                let filled = pct / BRIGHTNESS_BAR_SEGMENTS;
+                let bar: String = "█".repeat(filled) + &"░".repeat(BRIGHTNESS_BAR_SEGMENTS - filled);
+                let icon = if device.reachable { "!" } else { "?" };
+
+                ListItem::new(Line::from(vec![
+                    Span::styled(format!(" {} ", icon), Style::default().fg(theme.bright_red)),
+                    Span::styled(truncate_name(&device.name, 25), theme.normal()),
+                    Span::raw("  "),
+                    Span::styled(
+                        format!("{:<14}", device.kind.label()),
+                        Style::default().fg(theme.crystal_cyan),
+                    ),
+                    Span::styled(bar, Style::default().fg(theme.bright_red)),
+                    Span::styled(format!(" {:>3}%", pct), Style::default().fg(theme.foreground)),
+                    Span::raw("  "),
+                    Span::styled(device.product(), Style::default().fg(theme.dimmed)),
+                    Span::styled(
+                        if device.firmware.is_empty() {
+                            String::new()
+                        } else {
+                            format!("  fw {}", device.firmware)
+                        },
+                        Style::default().fg(theme.dimmed),
+                    ),
+                ]))
+            })
+            .collect()
+    };
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(theme.border())
+                .title(Span::styled(format!(" Batteries ≤ {}% ", threshold), theme.title())),
+        )
+        .style(theme.normal());
+
+    frame.render_widget(list, area);
+}
+
+/// "12 s ago", "5 min ago", "3 h ago", "2 d ago".
+fn format_ago(unix_secs: u64) -> String {
+    format!("{} ago", format_duration(unix_now().saturating_sub(unix_secs)))
+}
+
+fn draw_detail_pane(frame: &mut Frame, area: Rect, app: &App, theme: &FrostTheme) {
+    let label = |text: &str| Span::styled(format!(" {:<11}", text), Style::default().fg(theme.dimmed));
+    let value = |text: String| Span::styled(text, Style::default().fg(theme.foreground));
+    let row = |name: &str, text: String| Line::from(vec![label(name), value(text)]);
+    let or_dash = |text: &str| if text.is_empty() { "–".to_string() } else { text.to_string() };
+
+    let mut lines = Vec::new();
+    if let Some(device) = app.selected_device() {
+        lines.push(Line::from(Span::styled(
+            format!(" {}", device.name),
+            theme.title(),
+        )));
+        lines.push(row("ID", device.id.to_string()));
+        lines.push(row("Kind", device.kind.label().to_string()));
+        lines.push(row("Vendor", or_dash(&device.manufacturer)));
+        lines.push(row("Model", or_dash(&device.model)));
+        lines.push(row("Firmware", or_dash(&device.firmware)));
+        let power = match device.battery {
+            Some(b) => format!("{} ({}%)", device.power_source.label(), b),
+            None => device.power_source.label().to_string(),
+        };
+        lines.push(row("Power", power));
+        lines.push(row("Reachable", if device.reachable { "yes" } else { "no" }.to_string()));
+        lines.push(row("Last seen", device.last_seen.map_or("–".to_string(), format_ago)));
+    } else {
+        lines.push(Line::from(Span::styled(
+            " No device info yet",
+            Style::default().fg(theme.dimmed),
+        )));
+    }
+
+    // Raw state as the gateway reports it
+    if let (View::Lights, Some(light)) = (app.view, app.lights.get(app.selected)) {
+        lines.push(Line::from(""));
+        lines.push(row("Can", light.capabilities().join(", ")));
+        lines.push(row("On", light.on.to_string()));
+        if !light.plug {
+            lines.push(row("Dimmer", format!("{} / 254", light.brightness)));
+        }
+        if let Some(hex) = &light.color_hex {
+            lines.push(row("Hex", hex.clone()));
+        }
+        if let Some(mireds) = light.mireds {
+            lines.push(row("Mireds", format!("{} ({} K)", mireds, light.kelvin().unwrap_or(0))));
+        }
+        if let Some(hue) = light.hue {
+            lines.push(row("Hue", hue.to_string()));
+        }
+        if let Some(sat) = light.saturation {
+            lines.push(row("Saturation", sat.to_string()));
+        }
+        if let Some((x, y)) = light.color_xy {
+            lines.push(row("XY", format!("{}, {}", x, y)));
+        }
+    } else if let (View::Blinds, Some(blind)) = (app.view, app.blinds.get(app.selected_blind)) {
+        lines.push(Line::from(""));
+        lines.push(row("Position", format!("{} / 100", blind.position)));
+    }
+
+    let pane = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(theme.border())
+            .title(Span::styled(" Details ", theme.title())),
+    );
+
+    frame.render_widget(pane, area);
+}
+
+fn draw_gateway_popup(frame: &mut Frame, area: Rect, app: &App, theme: &FrostTheme) {
+    let label = |text: &str| Span::styled(format!(" {:<14}", text), Style::default().fg(theme.dimmed));
+    let value = |text: String| Span::styled(text, Style::default().fg(theme.foreground));
+    let row = |name: &str, text: String| Line::from(vec![label(name), value(text)]);
+    let or_dash = |text: &str| if text.is_empty() { "–".to_string() } else { text.to_string() };
+
+    let mut lines = Vec::new();
+    match &app.gateway {
+        Some(gateway) => {
+            lines.push(row("ID", or_dash(&gateway.id)));
+            lines.push(row("Firmware", or_dash(&gateway.firmware)));
+            lines.push(row("Clock", gateway.clock_label()));
+            lines.push(row("NTP server", or_dash(&gateway.ntp_server)));
+            lines.push(row("Commissioning", if gateway.commissioning { "open" } else { "off" }.to_string()));
+            lines.push(row("Set up", gateway.first_setup.map_or("–".to_string(), format_ago)));
+            lines.push(row("Checked", format_ago(gateway.fetched_at)));
+            if !app.identities.is_empty() {
+                let unused = app.unused_identities().len();
+                lines.push(row("Identities", format!("{} paired here, {} unused", app.identities.len(), unused)));
+            }
+            let warnings = gateway.warnings();
+            if !warnings.is_empty() {
+                lines.push(Line::from(""));
+            }
+            for warning in warnings {
+                lines.push(Line::from(Span::styled(
+                    format!(" ! {}", warning),
+                    Style::default().fg(theme.bright_red),
+                )));
+            }
+        }
+        None => lines.push(Line::from(Span::styled(
+            " Hämtar gateway-info...",
+            Style::default().fg(theme.dimmed),
+        ))),
+    }
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled(
+        " R refresh  ·  B reboot  ·  D forget unused  ·  G close",
+        Style::default().fg(theme.dimmed),
+    )));
+
+    let width = GATEWAY_POPUP_WIDTH.min(area.width);
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let x = area.width.saturating_sub(width) / 2;
+    let y = area.height.saturating_sub(height) / 2;
+    let popup_area = Rect::new(x, y, width, height);
+
+    frame.render_widget(Clear, popup_area);
+
+    let popup = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .title(Span::styled(" Gateway ", theme.title()))
+                .borders(Borders::ALL)
+                .border_style(theme.border()),
+        )
+        .style(theme.popup());
+
+    frame.render_widget(popup, popup_area);
+}
+
 fn draw_footer(frame: &mut Frame, area: Rect, theme: &FrostTheme) {
     let sep = Span::styled("  ", Style::default());
 
@@ -248,6 +656,12 @@ fn draw_footer(frame: &mut Frame, area: Rect, theme: &FrostTheme) {
         Span::styled("+/-", Style::default().fg(theme.ice_blue)),
         Span::styled(" color", Style::default().fg(theme.dimmed)),
         sep.clone(),
+        Span::styled("Tab", Style::default().fg(theme.ice_blue)),
+        Span::styled(" rooms", Style::default().fg(theme.dimmed)),
+        sep.clone(),
+        Span::styled("s", Style::default().fg(theme.ice_blue)),
+        Span::styled(" scenes", Style::default().fg(theme.dimmed)),
+        sep.clone(),
         Span::styled("?", Style::default().fg(theme.ice_blue)),
         Span::styled(" help", Style::default().fg(theme.dimmed)),
         sep.clone(),
@@ -316,6 +730,66 @@ fn draw_status_popup(frame: &mut Frame, area: Rect, msg: &str, theme: &FrostThem
     frame.render_widget(popup, popup_area);
 }
 
+fn draw_scene_picker(frame: &mut Frame, area: Rect, app: &App, selected: usize, theme: &FrostTheme) {
+    let choices = app.scene_choices();
+    let width = SCENE_PICKER_WIDTH.min(area.width);
+    let height = (choices.len() as u16 + 2).min(SCENE_PICKER_MAX_HEIGHT).min(area.height);
+    let x = area.width.saturating_sub(width) / 2;
+    let y = area.height.saturating_sub(height) / 2;
+    let popup_area = Rect::new(x, y, width, height);
+
+    frame.render_widget(Clear, popup_area);
+
+    // Keep the selection visible when there are more entries than rows.
+    let rows = height.saturating_sub(2) as usize;
+    let skip = selected.saturating_sub(rows.saturating_sub(1));
+
+    let items: Vec<ListItem> = choices
+        .iter()
+        .enumerate()
+        .skip(skip)
+        .take(rows)
+        .map(|(i, choice)| {
+            let style = if i == selected { theme.selected() } else { theme.normal() };
+            let line = match choice {
+                SceneChoice::Builtin(scene) => Line::from(vec![
+                    Span::styled(" ✦ ", Style::default().fg(theme.warm_yellow)),
+                    Span::styled(scene.name().to_string(), style),
+                ]),
+                SceneChoice::Mood(mood) => {
+                    let active = app
+                        .groups
+                        .iter()
+                        .any(|g| g.id == mood.group_id && g.mood_id == Some(mood.id));
+                    Line::from(vec![
+                        Span::styled(
+                            if active { " * " } else { " ❄ " },
+                            Style::default().fg(if active { theme.cold_green } else { theme.crystal_cyan }),
+                        ),
+                        Span::styled(mood.name.clone(), style),
+                        Span::styled(
+                            format!("  {}", app.group_name(mood.group_id)),
+                            Style::default().fg(theme.dimmed),
+                        ),
+                    ])
+                }
+            };
+            ListItem::new(line)
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .title(Span::styled(" Scenes & Moods ", theme.title()))
+                .borders(Borders::ALL)
+                .border_style(theme.border()),
+        )
+        .style(theme.popup());
+
+    frame.render_widget(list, popup_area);
+}
+
 fn draw_help_popup(frame: &mut Frame, area: Rect, theme: &FrostTheme) {
     let width = HELP_POPUP_WIDTH;
     let height = HELP_POPUP_HEIGHT;
@@ -329,17 +803,26 @@ fn draw_help_popup(frame: &mut Frame, area: Rect, theme: &FrostTheme) {
         Line::from(Span::styled("Navigation", Style::default().fg(theme.ice_blue).add_modifier(Modifier::BOLD))),
         Line::from("  j / ↓      Next light"),
         Line::from("  k / ↑      Previous light"),
+        Line::from("  Tab        Lights / Rooms / Blinds / Batteries"),
         Line::from(""),
         Line::from(Span::styled("Control", Style::default().fg(theme.ice_blue).add_modifier(Modifier::BOLD))),
         Line::from("  Space      Toggle on/off"),
         Line::from("  h / ←      Dim -10%"),
         Line::from("  l / →      Dim +10%"),
         Line::from("  PgUp/Dn    Dim ±25%"),
-        Line::from("  + / -      Color temp warmer/colder"),
+        Line::from("  + / -      Color temp warmer/colder (fine)"),
+        Line::from("  [ / ]      Cycle colour (colour bulbs)"),
+        Line::from("  Blinds:    h/l lower/raise, Space stop"),
+        Line::from("  i          Device details (model, firmware…)"),
+        Line::from("  G          Gateway info, reboot, identities"),
+        Line::from("  N          Rename light, blind or room"),
         Line::from(""),
         Line::from(Span::styled("Scenes", Style::default().fg(theme.warm_yellow).add_modifier(Modifier::BOLD))),
         Line::from("  a=On o=Off m=Movie b=Bright c=Cozy"),
         Line::from("  n=Night e=Evening r=Read g=Morning"),
+        Line::from("  s          Pick scene or gateway mood"),
+        Line::from("             (f in picker: slow fade, x cancels)"),
+        Line::from("  (Rooms view: applies to selected room)"),
         Line::from(""),
         Line::from(Span::styled("  Press ? or Esc to close", Style::default().fg(theme.dimmed))),
     ];
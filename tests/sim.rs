@@ -0,0 +1,247 @@
+//! `frostlux sim` on an ephemeral port, talked to over DTLS the way the app
+//! talks to a real gateway.
+
+use frostlux::coap::{self, Backend, RetryPolicy, Transport};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+const IDENTITY: &str = "frostlux-test";
+const PSK: &str = "frostlux-test-psk";
+/// Members of the big room: enough for its document to need several blocks.
+const BIG_ROOM_MEMBERS: u64 = 400;
+
+/// A running simulator, stopped when dropped.
+struct Sim {
+    child: Child,
+    host: String,
+    home: PathBuf,
+}
+
+impl Sim {
+    /// Start on a free port.
+    fn start(name: &str, extra: &[&str]) -> Self {
+        Self::start_at(name, 0, extra)
+    }
+
+    fn start_at(name: &str, port: u16, extra: &[&str]) -> Self {
+        let home = std::env::temp_dir().join(format!("frostlux-{}-{}.json", name, std::process::id()));
+        std::fs::write(&home, home_json().to_string()).unwrap();
+        let mut child = Command::new(env!("CARGO_BIN_EXE_frostlux"))
+            .args(["sim", "--port", &port.to_string(), "--identity", IDENTITY, "--psk", PSK, "--home"])
+            .arg(&home)
+            .args(extra)
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut first = String::new();
+        stdout.read_line(&mut first).unwrap();
+        // Keep reading, or its session threads die printing to a closed pipe.
+        std::thread::spawn(move || stdout.lines().count());
+        let host = first
+            .trim()
+            .strip_prefix("FrostLux sim: gateway listening on ")
+            .unwrap_or_else(|| panic!("unexpected sim output: {:?}", first))
+            .to_string();
+        Self { child, host, home }
+    }
+
+    fn port(&self) -> u16 {
+        self.host.rsplit(':').next().unwrap().parse().unwrap()
+    }
+
+    fn connect(&self, retry: RetryPolicy) -> Box<dyn Transport> {
+        let backend = Backend::Dtls {
+            host: self.host.clone(),
+            identity: IDENTITY.to_string(),
+            psk: PSK.to_string(),
+            retry,
+        };
+        backend.connect().unwrap()
+    }
+}
+
+impl Drop for Sim {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_file(&self.home);
+    }
+}
+
+/// Two bulbs in a normal room, and a room whose document is several KiB.
+fn home_json() -> Value {
+    let bulb = |id: u64, name: &str| {
+        json!({
+            "9003": id, "9001": name, "5750": 2, "9019": 1,
+            "3": { "0": "IKEA of Sweden", "1": "TRADFRI bulb E27 WS opal 1000lm" },
+            "3311": [{ "5850": 1, "5851": 200, "5711": 370 }]
+        })
+    };
+    let big_room: Vec<u64> = (65537..65537 + BIG_ROOM_MEMBERS).collect();
+    json!({
+        "devices": [bulb(65537, "Taklampa"), bulb(65538, "Golvlampa")],
+        "groups": [
+            { "9003": 131073, "9001": "Vardagsrum", "9018": { "15002": { "9003": [65537, 65538] } } },
+            { "9003": 131074, "9001": "Hela huset", "9018": { "15002": { "9003": big_room } } }
+        ]
+    })
+}
+
+fn get(transport: &mut dyn Transport, path: &str) -> Value {
+    serde_json::from_slice(&transport.get(path).unwrap()).unwrap()
+}
+
+/// GETs, including a block-wise one, and the pipelined listing.
+fn check_gets(transport: &mut dyn Transport) {
+    assert_eq!(get(transport, "15001"), json!([65537, 65538]));
+    assert_eq!(get(transport, "15001/65537")["9001"], "Taklampa");
+
+    let room = transport.get("15004/131074").unwrap();
+    assert!(room.len() > 2048, "only {} bytes, no Block2 needed", room.len());
+    let room: Value = serde_json::from_slice(&room).unwrap();
+    assert_eq!(room["9018"]["15002"]["9003"].as_array().unwrap().len() as u64, BIG_ROOM_MEMBERS);
+
+    let paths: Vec<String> = ["15004/131073", "15004/131074", "15001/65538"].map(String::from).to_vec();
+    for (path, result) in paths.iter().zip(transport.get_many(&paths)) {
+        let doc: Value = serde_json::from_slice(&result.unwrap()).unwrap();
+        assert_eq!(path.rsplit('/').next().unwrap(), doc["9003"].to_string());
+    }
+}
+
+#[test]
+fn get_block2_and_observe() {
+    let sim = Sim::start("sim", &[]);
+    let mut transport = sim.connect(RetryPolicy::default());
+    check_gets(transport.as_mut());
+
+    // Observe one bulb; once its current state is in, change it over a
+    // second session and wait for the notification.
+    let mut other = sim.connect(RetryPolicy::default());
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut seen = Vec::new();
+        let stop = AtomicBool::new(false);
+        let result = transport.observe(&["15001/65538".to_string()], &stop, &mut |path, payload| {
+            let doc: Value = serde_json::from_slice(payload).unwrap();
+            let brightness = doc["3311"][0]["5851"].as_u64().unwrap();
+            seen.push((path.to_string(), brightness));
+            if brightness == 200 {
+                other.put("15001/65538", br#"{"3311": [{"5851": 42}]}"#).unwrap();
+            }
+            brightness != 42
+        });
+        let _ = tx.send((result.map_err(|e| e.to_string()), seen));
+    });
+    let (result, seen) = rx.recv_timeout(Duration::from_secs(30)).expect("no notification in 30 s");
+    result.unwrap();
+    assert_eq!(seen.first(), Some(&("15001/65538".to_string(), 200)));
+    assert_eq!(seen.last(), Some(&("15001/65538".to_string(), 42)));
+}
+
+/// Short timeouts and more retransmissions than the default, so lossy runs
+/// retransmit often without being slow or flaky.
+const LOSSY: RetryPolicy = RetryPolicy {
+    ack_timeout: Duration::from_millis(200),
+    max_retransmit: 5,
+    reconnects: 2,
+};
+
+#[test]
+fn requests_survive_packet_loss() {
+    let sim = Sim::start("sim-loss", &["--loss", "20"]);
+    let mut transport = sim.connect(LOSSY);
+    for _ in 0..3 {
+        check_gets(transport.as_mut());
+    }
+}
+
+#[test]
+fn observe_survives_packet_loss() {
+    let sim = Sim::start("sim-observe-loss", &["--loss", "20"]);
+    // A registration that runs out of retransmissions ends the observation,
+    // where a request would just be retried: give it more room.
+    let mut transport = sim.connect(RetryPolicy { max_retransmit: 8, ..LOSSY });
+    let mut other = sim.connect(LOSSY);
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let (tx, rx) = mpsc::channel();
+    {
+        let stop = Arc::clone(&stop);
+        std::thread::spawn(move || {
+            let result = transport.observe(&["15001/65538".to_string()], &stop, &mut |_, payload| {
+                let doc: Value = serde_json::from_slice(payload).unwrap();
+                tx.send(doc["3311"][0]["5851"].as_u64().unwrap()).is_ok()
+            });
+            result.unwrap();
+        });
+    }
+    let first = rx.recv_timeout(Duration::from_secs(30)).expect("registration never answered");
+    assert_eq!(first, 200, "registration answers with the current state");
+
+    // Notifications aren't confirmable, so one can be lost for good: keep
+    // changing the bulb until a change gets through.
+    for brightness in 1..100 {
+        let patch = json!({ "3311": [{ "5851": brightness }] }).to_string();
+        other.put("15001/65538", patch.as_bytes()).unwrap();
+        if let Ok(seen) = rx.recv_timeout(Duration::from_millis(300)) {
+            assert!((1..=brightness).contains(&seen));
+            stop.store(true, Ordering::Relaxed);
+            return;
+        }
+    }
+    panic!("no notification got through");
+}
+
+#[test]
+fn requests_reconnect_after_the_gateway_restarts() {
+    let sim = Sim::start("sim-restart", &[]);
+    let retry = RetryPolicy {
+        ack_timeout: Duration::from_millis(200),
+        max_retransmit: 2,
+        reconnects: 1,
+    };
+    let mut transport = sim.connect(retry);
+    assert_eq!(get(transport.as_mut(), "15001/65537")["9001"], "Taklampa");
+
+    let port = sim.port();
+    drop(sim);
+    let _sim = Sim::start_at("sim-restart", port, &[]);
+    // The new process knows nothing of the old session: the request goes
+    // unanswered on it, and succeeds after a fresh handshake.
+    assert_eq!(get(transport.as_mut(), "15001/65538")["9001"], "Golvlampa");
+    check_gets(transport.as_mut());
+}
+
+#[test]
+fn observe_stops_when_asked() {
+    let sim = Sim::start("sim-stop", &[]);
+    let mut transport = sim.connect(RetryPolicy::default());
+    let stop = Arc::new(AtomicBool::new(false));
+    let (tx, rx) = mpsc::channel();
+    {
+        let stop = Arc::clone(&stop);
+        std::thread::spawn(move || {
+            let result = transport.observe(&["15001/65537".to_string()], &stop, &mut |_, _| true);
+            let _ = tx.send(result.map_err(|e| e.to_string()));
+        });
+    }
+    // Registered and idle: nothing more will arrive, so only `stop` ends it.
+    assert!(rx.recv_timeout(Duration::from_millis(500)).is_err());
+    stop.store(true, Ordering::Relaxed);
+    let result = rx.recv_timeout(Duration::from_secs(3)).expect("observe ignored stop");
+    result.unwrap();
+}
+
+#[test]
+fn identities_are_checked_with_a_handshake() {
+    let sim = Sim::start("sim-identity", &[]);
+    coap::check_identity(&sim.host, IDENTITY, PSK).unwrap();
+    assert!(coap::check_identity(&sim.host, IDENTITY, "wrong-psk").is_err());
+    assert!(coap::check_identity(&sim.host, "frostlux-unknown", PSK).is_err());
+}